@@ -1,29 +1,37 @@
 use axum::{
-    extract::State,
-    http::StatusCode,
-    Json,
+    extract::{FromRequestParts, State},
+    http::{request::Parts, StatusCode},
 };
+use axum_extra::TypedHeader;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use crate::db_constants::{Collections, UserFields};
-use crate::AppState;
+use crate::{error, AppState};
+use crate::error::SharedTierListError;
+use crate::extract::Json;
+use crate::error::SharedTierListError::{Conflict, Forbidden, StatusCodeError};
+use crate::email_verification::send_verification_email;
+use crate::email::{is_valid_email, normalize_email};
+use crate::models::User;
+use crate::refresh_token::issue_refresh_token;
+use crate::session::{create_session, session_is_active};
 use argon2::{
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2
 };
-use chrono::{Duration, Utc};
+use chrono::Utc;
 use headers::authorization::Bearer;
 use headers::Authorization;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::{doc, Document};
 use password_hash::rand_core::OsRng;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct Claims {
     sub: String,
     exp: i64,
+    /// Id of the server-side session; every access token minted for one login shares it.
+    jti: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -41,35 +49,49 @@ pub struct LoginRequest {
 
 #[derive(Debug, Serialize)]
 pub struct LoginResponse {
-    token: String,
-    user_id: String,
+    pub(crate) token: String,
+    pub(crate) refresh_token: String,
+    pub(crate) user_id: String,
 }
 
+pub fn hash_password(password: &str) -> error::Result<String> {
+    let argon2 = Argon2::default();
+    let salt = SaltString::generate(OsRng);
+
+    argon2.hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|_| StatusCodeError(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+/// Creates an unverified account and mails it a verification code; invitations already sent to the address show
+/// up as incoming once it is verified.
 pub async fn signup(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<SignupRequest>,
-) -> Result<StatusCode, StatusCode> {
-    let users = state.db.collection::<Document>(Collections::USERS);
-    let user_opt: Option<Document> = users
-        .find_one(doc! { UserFields::EMAIL: payload.email.clone() })
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+) -> error::Result<StatusCode> {
+    let email = normalize_email(&payload.email);
+
+    if !is_valid_email(&email) {
+        return Err(SharedTierListError::Validation("Not a valid email address".to_string()));
+    }
+
+    let user_opt = state.store.find_user_by_email(&email).await?;
 
     match user_opt {
-        Some(_) => Err(StatusCode::CONFLICT),
+        Some(_) => Err(Conflict("An account with this email already exists".to_string())),
         None => {
-            let argon2 = Argon2::default();
-            let salt = SaltString::generate(OsRng);
-            let password_hash = argon2.hash_password(payload.password.as_bytes(), &salt)
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-                .to_string();
-
-            users.insert_one(doc! {
-                UserFields::EMAIL: payload.email,
-                UserFields::DISPLAY_NAME: payload.display_name,
-                UserFields::PASSWD_HASH: password_hash,
-                UserFields::PROJECTS: [],
-            }).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            let user = User {
+                id: ObjectId::new(),
+                email,
+                display_name: payload.display_name,
+                passwd_hash: hash_password(&payload.password)?,
+                verified: false,
+                projects: vec![],
+            };
+
+            state.store.insert_user(&user).await?;
+
+            send_verification_email(&state, user.id, user.email).await?;
 
             Ok(StatusCode::CREATED)
         }
@@ -79,79 +101,316 @@ pub async fn signup(
 pub async fn login(
     State(state): State<Arc<AppState>>,
     Json(payload): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, StatusCode> {
-    let users = state.db.collection::<Document>(Collections::USERS);
-    let user_opt: Option<Document> = users.find_one(doc! {
-        UserFields::EMAIL: payload.email.clone()
-    }).await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+) -> error::Result<Json<LoginResponse>> {
+    let user_opt = state.store.find_user_by_email(&normalize_email(&payload.email)).await?;
 
     tracing::debug!("looked up user");
 
     let user = match user_opt {
-        Some(user_doc) => user_doc,
-        None => return Err(StatusCode::UNAUTHORIZED)
+        Some(user) => user,
+        None => return Err(StatusCodeError(StatusCode::UNAUTHORIZED))
     };
 
-    let stored_hash = user.get_str(UserFields::PASSWD_HASH)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    let parsed_hash = PasswordHash::new(stored_hash)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let parsed_hash = PasswordHash::new(&user.passwd_hash)
+        .map_err(|_| StatusCodeError(StatusCode::INTERNAL_SERVER_ERROR))?;
 
     tracing::debug!("parsed password hash");
 
     let argon2 = Argon2::default();
     if argon2.verify_password(payload.password.as_bytes(), &parsed_hash).is_err() {
-        return Err(StatusCode::UNAUTHORIZED);
+        return Err(StatusCodeError(StatusCode::UNAUTHORIZED));
+    }
+
+    if state.verification_policy.require_for_login && !user.verified {
+        return Err(Forbidden("Verify your email address before logging in".to_string()));
     }
 
+    let user_id = user.id;
+
+    let session_id = create_session(&state, user_id).await?;
+
+    let token = issue_access_token(&state, user_id, session_id)?;
+
+    // The session id doubles as the refresh token family.
+    let refresh_token = issue_refresh_token(&state, user_id, session_id).await?;
+
+    Ok(Json(LoginResponse {
+        token,
+        refresh_token,
+        user_id: user_id.to_string(),
+    }))
+}
+
+pub fn issue_access_token(
+    state: &AppState,
+    user_id: ObjectId,
+    session_id: ObjectId,
+) -> error::Result<String> {
     let expiration = Utc::now()
-        .checked_add_signed(Duration::minutes(15))
+        .checked_add_signed(state.access_token_lifetime)
         .unwrap()
         .timestamp();
 
-    tracing::debug!("{:?}", user);
-
-    let user_id = user.get_object_id(UserFields::ID)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.to_string();
+    encode_access_token(&state.jwt_secret_key, user_id, session_id, expiration)
+}
 
+fn encode_access_token(
+    secret: &str,
+    user_id: ObjectId,
+    session_id: ObjectId,
+    expiration: i64,
+) -> error::Result<String> {
     let claims = Claims {
-        sub: user_id.clone(),
+        sub: user_id.to_string(),
         exp: expiration,
+        jti: session_id.to_string(),
     };
 
     tracing::debug!("constructed claims");
 
-    let token = encode(
+    encode(
         &Header::default(),
         &claims,
-        &EncodingKey::from_secret(state.jwt_secret_key.as_ref())
-    ).unwrap();
+        &EncodingKey::from_secret(secret.as_ref())
+    ).map_err(|_| StatusCodeError(StatusCode::INTERNAL_SERVER_ERROR))
+}
 
-    Ok(Json(LoginResponse {
+/// Checks the token's signature and expiry and returns the user and session ids it names.
+fn decode_access_token(secret: &str, token: &str) -> error::Result<(ObjectId, ObjectId)> {
+    let claims = decode::<Claims>(
         token,
-        user_id
-    }))
+        &DecodingKey::from_secret(secret.as_ref()),
+        &Validation::default()
+    ).map_err(|_| StatusCodeError(StatusCode::UNAUTHORIZED))?.claims;
+
+    let user_id = ObjectId::parse_str(&claims.sub)
+        .map_err(|_| StatusCodeError(StatusCode::UNAUTHORIZED))?;
+    let session_id = ObjectId::parse_str(&claims.jti)
+        .map_err(|_| StatusCodeError(StatusCode::UNAUTHORIZED))?;
+
+    Ok((user_id, session_id))
 }
 
-pub async fn authenticate_user(
-    app_state: Arc<AppState>,
+/// Validates the bearer token and checks that its session has not been revoked.
+pub async fn authenticate_session(
+    app_state: &AppState,
     auth: Authorization<Bearer>,
-) -> Result<Document, StatusCode> {
-     let claims = decode::<Claims>(
-         auth.token(),
-         &DecodingKey::from_secret(app_state.jwt_secret_key.as_ref()),
-         &Validation::default()
-    ).map_err(|_| StatusCode::UNAUTHORIZED)?;
+) -> error::Result<(ObjectId, ObjectId)> {
+    let (user_id, session_id) = decode_access_token(&app_state.jwt_secret_key, auth.token())?;
 
-    let users = app_state.db.collection::<Document>(Collections::USERS);
+    let is_active = session_is_active(app_state, user_id, session_id).await?;
 
-    let user_opt = users
-        .find_one(doc! { UserFields::ID: claims.claims.sub })
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if is_active {
+        Ok((user_id, session_id))
+    } else {
+        Err(StatusCodeError(StatusCode::UNAUTHORIZED))
+    }
+}
 
-    match user_opt {
-        Some(user) => Ok(user),
-        None => Err(StatusCode::UNAUTHORIZED)
+/// The session behind the request's bearer token, for handlers that only need the ids.
+///
+/// Cached in the request extensions, so extracting it more than once checks the session once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthSession {
+    pub user_id: ObjectId,
+    pub session_id: ObjectId,
+}
+
+impl FromRequestParts<Arc<AppState>> for AuthSession {
+    type Rejection = SharedTierListError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app_state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(session) = parts.extensions.get::<AuthSession>() {
+            return Ok(*session);
+        }
+
+        let TypedHeader(auth) = TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, app_state)
+            .await
+            .map_err(|_| StatusCodeError(StatusCode::UNAUTHORIZED))?;
+
+        let (user_id, session_id) = authenticate_session(app_state, auth).await?;
+        let session = AuthSession {
+            user_id,
+            session_id,
+        };
+
+        parts.extensions.insert(session);
+        Ok(session)
+    }
+}
+
+/// The caller behind the request's bearer token; the only trusted source of identity.
+///
+/// Like `AuthSession`, the user lookup is cached in the request extensions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthenticatedUser {
+    pub user_id: ObjectId,
+    pub session_id: ObjectId,
+    pub email: String,
+    pub display_name: String,
+    pub verified: bool,
+    pub projects: Vec<ObjectId>,
+}
+
+impl AuthenticatedUser {
+    fn from_user(session_id: ObjectId, user: User) -> Self {
+        AuthenticatedUser {
+            user_id: user.id,
+            session_id,
+            email: user.email,
+            display_name: user.display_name,
+            verified: user.verified,
+            projects: user.projects,
+        }
+    }
+}
+
+impl FromRequestParts<Arc<AppState>> for AuthenticatedUser {
+    type Rejection = SharedTierListError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app_state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(user) = parts.extensions.get::<AuthenticatedUser>() {
+            return Ok(user.clone());
+        }
+
+        let session = AuthSession::from_request_parts(parts, app_state).await?;
+
+        let user = app_state.store.find_user(session.user_id)
+            .await?
+            .ok_or(StatusCodeError(StatusCode::UNAUTHORIZED))?;
+
+        let user = AuthenticatedUser::from_user(session.session_id, user);
+
+        parts.extensions.insert(user.clone());
+        Ok(user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::email_verification::VerificationPolicy;
+    use crate::mailer::LogMailer;
+    use crate::store::MemoryStore;
+    use axum::http::Request;
+    use chrono::Duration;
+    use std::collections::HashMap;
+    use tokio::sync::{broadcast, Mutex};
+
+    const SECRET: &str = "test-secret";
+
+    /// State with an empty store, so any lookup the extractors make finds nothing.
+    fn empty_app_state() -> Arc<AppState> {
+        Arc::new(AppState {
+            store: Arc::new(MemoryStore::new()),
+            jwt_secret_key: SECRET.to_string(),
+            live_sessions: Mutex::new(HashMap::new()),
+            session_revocations: broadcast::channel(1).0,
+            mailer: Arc::new(LogMailer),
+            verification_policy: VerificationPolicy {
+                require_for_login: false,
+                require_for_invitations: false,
+            },
+            access_token_lifetime: Duration::minutes(15),
+            refresh_token_lifetime: Duration::days(30),
+            broadcast_channel_capacity: 1,
+        })
+    }
+
+    fn in_one_hour() -> i64 {
+        Utc::now().timestamp() + 3600
+    }
+
+    fn user(user_id: ObjectId, projects: Vec<ObjectId>) -> User {
+        User {
+            id: user_id,
+            email: "user@example.com".to_string(),
+            display_name: "User".to_string(),
+            passwd_hash: "hash".to_string(),
+            verified: false,
+            projects,
+        }
+    }
+
+    #[test]
+    fn access_token_round_trips() {
+        let user_id = ObjectId::new();
+        let session_id = ObjectId::new();
+        let token = encode_access_token(SECRET, user_id, session_id, in_one_hour()).unwrap();
+
+        assert_eq!(decode_access_token(SECRET, &token).unwrap(), (user_id, session_id));
+    }
+
+    #[test]
+    fn access_token_signed_with_another_secret_is_rejected() {
+        let token = encode_access_token("other-secret", ObjectId::new(), ObjectId::new(), in_one_hour()).unwrap();
+
+        assert_eq!(decode_access_token(SECRET, &token).unwrap_err().status_code(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn expired_access_token_is_rejected() {
+        let an_hour_ago = Utc::now().timestamp() - 3600;
+        let token = encode_access_token(SECRET, ObjectId::new(), ObjectId::new(), an_hour_ago).unwrap();
+
+        assert_eq!(decode_access_token(SECRET, &token).unwrap_err().status_code(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn access_token_with_malformed_ids_is_rejected() {
+        let claims = Claims {
+            sub: "not-an-object-id".to_string(),
+            exp: in_one_hour(),
+            jti: ObjectId::new().to_string(),
+        };
+        let token = encode(&Header::default(), &claims, &EncodingKey::from_secret(SECRET.as_ref())).unwrap();
+
+        assert_eq!(decode_access_token(SECRET, &token).unwrap_err().status_code(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn authenticated_user_is_built_from_user() {
+        let user_id = ObjectId::new();
+        let session_id = ObjectId::new();
+        let project_id = ObjectId::new();
+        let user = AuthenticatedUser::from_user(session_id, user(user_id, vec![project_id]));
+
+        assert_eq!(user, AuthenticatedUser {
+            user_id,
+            session_id,
+            email: "user@example.com".to_string(),
+            display_name: "User".to_string(),
+            verified: false,
+            projects: vec![project_id],
+        });
+    }
+
+    #[tokio::test]
+    async fn cached_user_is_reused_without_a_lookup() {
+        let app_state = empty_app_state();
+        let cached = AuthenticatedUser::from_user(ObjectId::new(), user(ObjectId::new(), vec![]));
+
+        let (mut parts, _) = Request::new(()).into_parts();
+        parts.extensions.insert(cached.clone());
+
+        let user = AuthenticatedUser::from_request_parts(&mut parts, &app_state).await;
+
+        assert_eq!(user.unwrap(), cached);
+    }
+
+    #[tokio::test]
+    async fn missing_bearer_token_is_unauthorized() {
+        let app_state = empty_app_state();
+        let (mut parts, _) = Request::new(()).into_parts();
+
+        let session = AuthSession::from_request_parts(&mut parts, &app_state).await;
+
+        assert_eq!(session.unwrap_err().status_code(), StatusCode::UNAUTHORIZED);
     }
 }
@@ -0,0 +1,179 @@
+use crate::error;
+use crate::error::SharedTierListError::{Forbidden, NotFound};
+use crate::models::Project;
+use crate::store::Store;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProjectRole {
+    Owner,
+    Editor,
+    Commenter,
+    Viewer,
+}
+
+impl ProjectRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProjectRole::Owner => "owner",
+            ProjectRole::Editor => "editor",
+            ProjectRole::Commenter => "commenter",
+            ProjectRole::Viewer => "viewer",
+        }
+    }
+
+    pub fn can_edit(&self) -> bool {
+        matches!(self, ProjectRole::Owner | ProjectRole::Editor)
+    }
+
+    pub fn can_manage_members(&self) -> bool {
+        matches!(self, ProjectRole::Owner)
+    }
+}
+
+/// Works out a user's role on a project, if they are a member at all.
+///
+/// Contributors without an entry in `member_roles` predate roles and keep full edit access.
+pub fn project_role(project: &Project, user_id: ObjectId) -> Option<ProjectRole> {
+    if project.owner == user_id {
+        return Some(ProjectRole::Owner);
+    }
+
+    if !project.contributors.contains(&user_id) {
+        return None;
+    }
+
+    let role = project.member_roles.get(&user_id.to_hex())
+        .copied()
+        .unwrap_or(ProjectRole::Editor);
+
+    match role {
+        // Only the `owner` field can make someone an owner.
+        ProjectRole::Owner => Some(ProjectRole::Editor),
+        role => Some(role),
+    }
+}
+
+/// Loads a project on behalf of a user, failing with `NotFound` or `Forbidden`.
+pub async fn authorize_project(
+    store: &dyn Store,
+    user_id: ObjectId,
+    project_id: ObjectId,
+) -> error::Result<(Project, ProjectRole)> {
+    let project = store.find_project(project_id)
+        .await?
+        .filter(|project| !project.is_trashed())
+        .ok_or_else(|| NotFound("Project not found".to_string()))?;
+
+    match project_role(&project, user_id) {
+        Some(role) => Ok((project, role)),
+        None => Err(Forbidden("You are not a member of this project".to_string())),
+    }
+}
+
+/// Like `authorize_project`, but only lets through members who may manage the member list.
+pub async fn authorize_member_management(
+    store: &dyn Store,
+    user_id: ObjectId,
+    project_id: ObjectId,
+) -> error::Result<Project> {
+    let (project, role) = authorize_project(store, user_id, project_id).await?;
+
+    if role.can_manage_members() {
+        Ok(project)
+    } else {
+        Err(Forbidden("Only the project owner can manage members".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures;
+    use std::collections::HashMap;
+
+    fn project<const N: usize>(
+        owner: ObjectId,
+        contributors: Vec<ObjectId>,
+        member_roles: [(ObjectId, ProjectRole); N],
+    ) -> Project {
+        Project {
+            contributors,
+            member_roles: member_roles.into_iter()
+                .map(|(member_id, role)| (member_id.to_hex(), role))
+                .collect::<HashMap<_, _>>(),
+            ..test_fixtures::project(owner)
+        }
+    }
+
+    #[test]
+    fn owner_has_owner_role() {
+        let owner = ObjectId::new();
+        let project = project(owner, vec![ObjectId::new()], []);
+
+        assert_eq!(project_role(&project, owner), Some(ProjectRole::Owner));
+    }
+
+    #[test]
+    fn contributor_without_role_is_editor() {
+        let contributor = ObjectId::new();
+        let project = project(ObjectId::new(), vec![ObjectId::new(), contributor], []);
+
+        assert_eq!(project_role(&project, contributor), Some(ProjectRole::Editor));
+    }
+
+    #[test]
+    fn contributor_gets_stored_role() {
+        let viewer = ObjectId::new();
+        let commenter = ObjectId::new();
+        let project = project(
+            ObjectId::new(),
+            vec![viewer, commenter],
+            [(viewer, ProjectRole::Viewer), (commenter, ProjectRole::Commenter)],
+        );
+
+        assert_eq!(project_role(&project, viewer), Some(ProjectRole::Viewer));
+        assert_eq!(project_role(&project, commenter), Some(ProjectRole::Commenter));
+    }
+
+    #[test]
+    fn stored_owner_role_does_not_grant_ownership() {
+        let contributor = ObjectId::new();
+        let project = project(
+            ObjectId::new(),
+            vec![contributor],
+            [(contributor, ProjectRole::Owner)],
+        );
+
+        assert_eq!(project_role(&project, contributor), Some(ProjectRole::Editor));
+    }
+
+    #[test]
+    fn role_without_membership_grants_nothing() {
+        let former_contributor = ObjectId::new();
+        let project = project(
+            ObjectId::new(),
+            vec![],
+            [(former_contributor, ProjectRole::Editor)],
+        );
+
+        assert_eq!(project_role(&project, former_contributor), None);
+    }
+
+    #[test]
+    fn stranger_has_no_role() {
+        let project = project(ObjectId::new(), vec![ObjectId::new()], []);
+
+        assert_eq!(project_role(&project, ObjectId::new()), None);
+    }
+
+    #[test]
+    fn only_owners_and_editors_can_edit() {
+        assert!(ProjectRole::Owner.can_edit());
+        assert!(ProjectRole::Editor.can_edit());
+        assert!(!ProjectRole::Commenter.can_edit());
+        assert!(!ProjectRole::Viewer.can_edit());
+    }
+}
@@ -2,24 +2,91 @@ pub enum Collections {}
 impl Collections {
     pub const USERS: &'static str = "users";
     pub const PROJECTS: &'static str = "projects";
+    pub const REFRESH_TOKENS: &'static str = "refresh_tokens";
+    pub const SESSIONS: &'static str = "sessions";
+    pub const PASSWORD_RESETS: &'static str = "password_resets";
+    pub const EMAIL_VERIFICATIONS: &'static str = "email_verifications";
+    pub const INVITATIONS: &'static str = "invitations";
+    pub const INVITE_LINKS: &'static str = "invite_links";
+    pub const REVISIONS: &'static str = "revisions";
 }
 
 pub enum UserFields {}
 impl UserFields {
     pub const ID: &'static str = "_id";
     pub const EMAIL: &'static str = "email";
-    pub const DISPLAY_NAME: &'static str = "display_name";
     pub const PASSWD_HASH: &'static str = "passwd_hash";
+    pub const VERIFIED: &'static str = "verified";
     pub const PROJECTS: &'static str = "projects";
 }
 
 pub enum ProjectFields {}
 impl ProjectFields {
     pub const ID: &'static str = "_id";
-    pub const NAME: &'static str = "name";
     pub const TEMPLATE_LINK: &'static str = "template_link";
-    pub const OWNER: &'static str = "owner";
     pub const CONTRIBUTORS: &'static str = "contributors";
+    pub const MEMBER_ROLES: &'static str = "member_roles";
     pub const TIER_CONTAINER_HTML: &'static str = "tier_container_html";
     pub const IMAGE_CAROUSEL_HTML: &'static str = "image_carousel_html";
+    pub const TIER_LIST: &'static str = "tier_list";
+    pub const REVISION: &'static str = "revision";
+    pub const VERSION: &'static str = "version";
+    pub const DELETED_AT: &'static str = "deleted_at";
+    pub const OWNER: &'static str = "owner";
+}
+
+pub enum RevisionFields {}
+impl RevisionFields {
+    pub const ID: &'static str = "_id";
+    pub const PROJECT_ID: &'static str = "project_id";
+    pub const SEQUENCE: &'static str = "sequence";
+}
+
+pub enum RefreshTokenFields {}
+impl RefreshTokenFields {
+    pub const FAMILY_ID: &'static str = "family_id";
+    pub const TOKEN_HASH: &'static str = "token_hash";
+    pub const USED: &'static str = "used";
+    pub const REVOKED: &'static str = "revoked";
+}
+
+pub enum SessionFields {}
+impl SessionFields {
+    pub const ID: &'static str = "_id";
+    pub const USER_ID: &'static str = "user_id";
+    pub const LAST_REFRESHED_AT: &'static str = "last_refreshed_at";
+    pub const REVOKED: &'static str = "revoked";
+}
+
+/// Shared by the `password_resets` and `email_verifications` collections.
+pub enum EmailTokenFields {}
+impl EmailTokenFields {
+    pub const USER_ID: &'static str = "user_id";
+    pub const TOKEN_HASH: &'static str = "token_hash";
+    pub const EXPIRES_AT: &'static str = "expires_at";
+    pub const USED: &'static str = "used";
+    pub const CREATED_AT: &'static str = "created_at";
+}
+
+pub enum InvitationFields {}
+impl InvitationFields {
+    pub const ID: &'static str = "_id";
+    pub const EMAIL: &'static str = "email";
+    pub const PROJECT_ID: &'static str = "project_id";
+    pub const INVITED_BY: &'static str = "invited_by";
+    pub const STATUS: &'static str = "status";
+    pub const CREATED_AT: &'static str = "created_at";
+    pub const EXPIRES_AT: &'static str = "expires_at";
+    pub const RESPONDED_AT: &'static str = "responded_at";
+}
+
+pub enum InviteLinkFields {}
+impl InviteLinkFields {
+    pub const ID: &'static str = "_id";
+    pub const PROJECT_ID: &'static str = "project_id";
+    pub const TOKEN_HASH: &'static str = "token_hash";
+    pub const EXPIRES_AT: &'static str = "expires_at";
+    pub const MAX_USES: &'static str = "max_uses";
+    pub const USES: &'static str = "uses";
+    pub const REVOKED: &'static str = "revoked";
 }
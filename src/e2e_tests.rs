@@ -0,0 +1,687 @@
+use crate::email_verification::VerificationPolicy;
+use crate::test_harness::TestApp;
+use http::StatusCode;
+use serde_json::json;
+
+#[tokio::test]
+async fn invited_member_edit_reaches_the_owner() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+    let member = app.signup_and_login("member@example.com").await;
+
+    let project_id = app.create_project(&owner, &[&member.email]).await;
+
+    let (status, body) = app.post("/invitations", Some(&member.token), json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let invitation_id = body["invitations"][0]["invitation_id"].clone();
+
+    let (status, body) = app.post("/invitations/accept", Some(&member.token), json!({
+        "invitation_id": invitation_id,
+    })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    let mut owner_socket = app.connect_ws(&owner.token).await;
+    let mut member_socket = app.connect_ws(&member.token).await;
+
+    assert_eq!(owner_socket.open_project(&project_id).await["type"], "project_opened");
+    let opened = member_socket.open_project(&project_id).await;
+    assert_eq!(opened["type"], "project_opened");
+    assert_eq!(opened["contents"]["tier_container_html"], "<div>tiers</div>");
+
+    member_socket.send(json!({
+        "action": "edit_project",
+        "request_id": "edit-1",
+        "tier_container_html": "<div>edited</div>",
+        "image_carousel_html": "<div>images</div>",
+    })).await;
+
+    let ack = member_socket.recv_type("ack").await;
+    assert_eq!(ack["request_id"], "edit-1");
+
+    let update = owner_socket.recv_type("project_updated").await;
+    assert_eq!(update["project_id"], project_id);
+    assert_eq!(update["contents"]["tier_container_html"], "<div>edited</div>");
+
+    let (status, body) = app.post("/open-project", Some(&owner.token), json!({ "project_id": project_id })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["tier_container_html"], "<div>edited</div>");
+}
+
+#[tokio::test]
+async fn outsider_is_denied_over_the_socket() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+    let outsider = app.signup_and_login("outsider@example.com").await;
+
+    let project_id = app.create_project(&owner, &[]).await;
+
+    let mut socket = app.connect_ws(&outsider.token).await;
+    let denied = socket.open_project(&project_id).await;
+
+    assert_eq!(denied["type"], "permission_denied");
+    assert_eq!(denied["project_id"], project_id);
+}
+
+#[tokio::test]
+async fn socket_closes_after_repeated_permission_violations() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+    let outsider = app.signup_and_login("outsider@example.com").await;
+
+    let project_id = app.create_project(&owner, &[]).await;
+
+    let mut socket = app.connect_ws(&outsider.token).await;
+    for _ in 0..3 {
+        assert_eq!(socket.open_project(&project_id).await["type"], "permission_denied");
+    }
+
+    let close = socket.recv_close().await;
+    assert_eq!(close.reason.as_str(), "Too many permission violations");
+}
+
+#[tokio::test]
+async fn viewer_edits_are_refused_without_closing_the_socket() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+    let viewer = app.signup_and_login("viewer@example.com").await;
+
+    let project_id = app.create_project(&owner, &[]).await;
+    let token = app.create_invite_link(&owner, &project_id, json!({ "role": "viewer" })).await;
+    let (status, body) = app.post(&format!("/join/{token}"), Some(&viewer.token), json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    let mut socket = app.connect_ws(&viewer.token).await;
+    assert_eq!(socket.open_project(&project_id).await["type"], "project_opened");
+
+    // More refusals than it takes to close a socket that reaches for projects it is not a member of.
+    for attempt in 0..4 {
+        socket.send(json!({
+            "action": "edit_project",
+            "request_id": format!("edit-{attempt}"),
+            "tier_container_html": "<div>edited</div>",
+            "image_carousel_html": "<div>images</div>",
+        })).await;
+        let refused = socket.recv().await;
+        assert_eq!(refused["type"], "error", "{refused}");
+        assert_eq!(refused["code"], "read_only");
+        assert_eq!(refused["request_id"], format!("edit-{attempt}"));
+
+        socket.send(json!({ "action": "add_tier", "request_id": format!("op-{attempt}"), "tier_id": "s", "label": "S", "color": "#ff7f7f" })).await;
+        let refused = socket.recv().await;
+        assert_eq!(refused["code"], "read_only", "{refused}");
+    }
+
+    // Still open and still allowed to view.
+    assert_eq!(socket.open_project(&project_id).await["type"], "project_opened");
+}
+
+#[tokio::test]
+async fn invite_link_joins_with_its_role_until_used_up() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+    let first = app.signup_and_login("first@example.com").await;
+    let second = app.signup_and_login("second@example.com").await;
+
+    let project_id = app.create_project(&owner, &[]).await;
+    let token = app.create_invite_link(&owner, &project_id, json!({ "role": "commenter", "max_uses": 1 })).await;
+
+    let (status, body) = app.post(&format!("/join/{token}"), Some(&first.token), json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["project_id"], project_id);
+    assert_eq!(body["role"], "commenter");
+
+    let (status, body) = app.post("/open-project", Some(&first.token), json!({ "project_id": project_id })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    let (status, body) = app.post(&format!("/join/{token}"), Some(&second.token), json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{body}");
+
+    let (status, _) = app.post("/open-project", Some(&second.token), json!({ "project_id": project_id })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn revoked_invite_link_cannot_be_used() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+    let joiner = app.signup_and_login("joiner@example.com").await;
+
+    let project_id = app.create_project(&owner, &[]).await;
+    let token = app.create_invite_link(&owner, &project_id, json!({ "role": "editor" })).await;
+
+    let (_, body) = app.post("/invite-links", Some(&owner.token), json!({ "project_id": project_id })).await;
+    let link_id = body["links"][0]["link_id"].clone();
+
+    let (status, body) = app.post("/revoke-invite-link", Some(&owner.token), json!({
+        "project_id": project_id,
+        "link_id": link_id,
+    })).await;
+    assert_eq!(status, StatusCode::NO_CONTENT, "{body}");
+
+    let (status, body) = app.post(&format!("/join/{token}"), Some(&joiner.token), json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{body}");
+
+    let (status, _) = app.post("/open-project", Some(&joiner.token), json!({ "project_id": project_id })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn requests_without_a_token_are_unauthorized() {
+    let app = TestApp::spawn().await;
+
+    let (status, body) = app.post("/open-project-list", None, json!({ "template_link": "template" })).await;
+
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["code"], "unauthorized");
+    assert!(body["request_id"].is_string());
+}
+
+#[tokio::test]
+async fn malformed_bodies_get_the_uniform_error_shape() {
+    let app = TestApp::spawn().await;
+
+    let (status, body) = app.post_raw("/login", None, "{\"email\": ".to_string()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+    assert_eq!(body["code"], "validation_failed");
+    assert!(body["message"].as_str().is_some_and(|message| !message.is_empty()));
+    assert!(body["request_id"].is_string());
+
+    let (status, body) = app.post("/login", None, json!({ "email": 42 })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+    assert_eq!(body["code"], "validation_failed");
+}
+
+#[tokio::test]
+async fn mailed_code_verifies_the_account() {
+    let app = TestApp::spawn_with_policy(VerificationPolicy {
+        require_for_login: true,
+        require_for_invitations: false,
+    }).await;
+
+    app.signup("new@example.com").await;
+    assert_eq!(app.login("new@example.com").await.0, StatusCode::FORBIDDEN);
+
+    let code = app.mailer.last_code_for("new@example.com").unwrap();
+    let (status, body) = app.post("/verify-email", None, json!({ "token": code })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    assert_eq!(app.login("new@example.com").await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn signup_rejects_a_malformed_email() {
+    let app = TestApp::spawn().await;
+
+    let (status, body) = app.post("/signup", None, json!({
+        "email": "not-an-address",
+        "display_name": "Someone",
+        "password": "correct horse battery staple",
+    })).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+    assert_eq!(body["code"], "validation_failed");
+    assert!(app.mailer.last_code_for("not-an-address").is_none());
+}
+
+#[tokio::test]
+async fn email_case_and_spacing_do_not_split_accounts() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+
+    app.create_project(&owner, &[" Alice@Example.com"]).await;
+
+    let alice = app.signup_and_login("alice@example.com").await;
+
+    let (status, body) = app.post("/signup", None, json!({
+        "email": "ALICE@example.com ",
+        "display_name": "Alice again",
+        "password": "another password",
+    })).await;
+    assert_eq!(status, StatusCode::CONFLICT, "{body}");
+
+    assert_eq!(app.login("Alice@EXAMPLE.com").await.0, StatusCode::OK);
+
+    let (status, body) = app.post("/invitations", Some(&alice.token), json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["invitations"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn invitations_stay_hidden_until_the_email_is_verified() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+
+    app.signup("invitee@example.com").await;
+    let (status, body) = app.login("invitee@example.com").await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let token = body["token"].as_str().unwrap().to_string();
+
+    app.create_project(&owner, &["invitee@example.com"]).await;
+
+    let (status, body) = app.post("/invitations", Some(&token), json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN, "{body}");
+
+    app.verify("invitee@example.com").await;
+
+    let (status, body) = app.post("/invitations", Some(&token), json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["invitations"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn invitation_sent_before_signup_is_incoming_after_verifying() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+
+    let project_id = app.create_project(&owner, &["later@example.com"]).await;
+
+    let newcomer = app.signup_and_login("later@example.com").await;
+
+    let (_, body) = app.post("/open-project-list", Some(&newcomer.token), json!({ "template_link": "template" })).await;
+    assert_eq!(body["projects"], json!([]));
+
+    let (status, body) = app.post("/invitations", Some(&newcomer.token), json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["invitations"].as_array().unwrap().len(), 1);
+
+    let (status, body) = app.post("/invitations/accept", Some(&newcomer.token), json!({
+        "invitation_id": body["invitations"][0]["invitation_id"],
+    })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    let (status, body) = app.post("/open-project", Some(&newcomer.token), json!({ "project_id": project_id })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+}
+
+#[tokio::test]
+async fn password_reset_uses_up_older_codes_and_ends_sessions() {
+    let app = TestApp::spawn().await;
+    let user = app.signup_and_login("user@example.com").await;
+
+    let (status, _) = app.post("/password-reset/request", None, json!({ "email": "user@example.com" })).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let older_code = app.mailer.last_code_for("user@example.com").unwrap();
+
+    app.post("/password-reset/request", None, json!({ "email": "user@example.com" })).await;
+    let code = app.mailer.last_code_for("user@example.com").unwrap();
+    assert_ne!(code, older_code);
+
+    let (status, body) = app.post("/password-reset/confirm", None, json!({
+        "token": code,
+        "new_password": "a brand new password",
+    })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    for used_code in [&code, &older_code] {
+        let (status, body) = app.post("/password-reset/confirm", None, json!({
+            "token": used_code,
+            "new_password": "yet another password",
+        })).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+    }
+
+    let (status, _) = app.post("/sessions", Some(&user.token), json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = app.post("/login", None, json!({
+        "email": "user@example.com",
+        "password": "a brand new password",
+    })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+}
+
+#[tokio::test]
+async fn password_reset_mails_are_rate_limited() {
+    let app = TestApp::spawn().await;
+    app.signup_and_login("user@example.com").await;
+    let mails_before = app.mailer.mails_to("user@example.com").len();
+
+    for _ in 0..8 {
+        let (status, _) = app.post("/password-reset/request", None, json!({ "email": "user@example.com" })).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+
+    assert_eq!(app.mailer.mails_to("user@example.com").len() - mails_before, 5);
+}
+
+#[tokio::test]
+async fn refresh_token_rotates_once() {
+    let app = TestApp::spawn().await;
+    let user = app.signup_and_login("user@example.com").await;
+
+    let (status, body) = app.post("/refresh", None, json!({ "refresh_token": user.refresh_token })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_ne!(body["refresh_token"], user.refresh_token.as_str());
+
+    // Reusing the rotated token ends the session, so the fresh access token stops working too.
+    let (status, _) = app.post("/refresh", None, json!({ "refresh_token": user.refresh_token })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let token = body["token"].as_str().unwrap();
+    let (status, _) = app.post("/sessions", Some(token), json!({})).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn revoking_a_session_closes_its_socket() {
+    let app = TestApp::spawn().await;
+    let user = app.signup_and_login("user@example.com").await;
+
+    let mut socket = app.connect_ws(&user.token).await;
+
+    let (status, body) = app.post("/revoke-all-sessions", Some(&user.token), json!({})).await;
+    assert_eq!(status, StatusCode::NO_CONTENT, "{body}");
+
+    assert_eq!(socket.recv_close().await.reason.as_str(), "Session revoked");
+}
+
+#[tokio::test]
+async fn refresh_token_reuse_closes_the_session_socket() {
+    let app = TestApp::spawn().await;
+    let user = app.signup_and_login("user@example.com").await;
+
+    let mut socket = app.connect_ws(&user.token).await;
+
+    let (status, body) = app.post("/refresh", None, json!({ "refresh_token": user.refresh_token })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let (status, _) = app.post("/refresh", None, json!({ "refresh_token": user.refresh_token })).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    assert_eq!(socket.recv_close().await.reason.as_str(), "Session revoked");
+}
+
+#[tokio::test]
+async fn deleting_a_project_removes_it_and_notifies_open_sockets() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+    let member = app.signup_and_login("member@example.com").await;
+    let invitee = app.signup_and_login("invitee@example.com").await;
+
+    let project_id = app.create_project(&owner, &[&member.email, &invitee.email]).await;
+
+    let (_, body) = app.post("/invitations", Some(&member.token), json!({})).await;
+    let invitation_id = body["invitations"][0]["invitation_id"].clone();
+    app.post("/invitations/accept", Some(&member.token), json!({ "invitation_id": invitation_id })).await;
+
+    let mut member_socket = app.connect_ws(&member.token).await;
+    assert_eq!(member_socket.open_project(&project_id).await["type"], "project_opened");
+
+    let (status, body) = app.post("/delete_project", Some(&owner.token), json!({ "project_id": project_id })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    let deleted = member_socket.recv_type("project_deleted").await;
+    assert_eq!(deleted["project_id"], project_id);
+
+    let (status, _) = app.post("/open-project", Some(&owner.token), json!({ "project_id": project_id })).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (_, body) = app.post("/open-project-list", Some(&member.token), json!({ "template_link": "template" })).await;
+    assert_eq!(body["projects"], json!([]));
+
+    let (_, body) = app.post("/invitations", Some(&invitee.token), json!({})).await;
+    assert_eq!(body["invitations"], json!([]));
+
+    let (status, _) = app.post("/delete_project", Some(&owner.token), json!({ "project_id": project_id })).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn trashed_project_is_hidden_until_restored() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+
+    let project_id = app.create_project(&owner, &[]).await;
+    app.post("/delete_project", Some(&owner.token), json!({ "project_id": project_id })).await;
+
+    let (status, body) = app.post("/trashed-projects", Some(&owner.token), json!({})).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["projects"][0]["project_id"], project_id);
+
+    let (_, body) = app.post("/open-project-list", Some(&owner.token), json!({ "template_link": "template" })).await;
+    assert_eq!(body["projects"], json!([]));
+
+    let mut socket = app.connect_ws(&owner.token).await;
+    let refused = socket.open_project(&project_id).await;
+    assert_eq!(refused["type"], "error");
+
+    let (status, body) = app.post("/restore-project", Some(&owner.token), json!({ "project_id": project_id })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    let (_, body) = app.post("/trashed-projects", Some(&owner.token), json!({})).await;
+    assert_eq!(body["projects"], json!([]));
+
+    assert_eq!(socket.open_project(&project_id).await["type"], "project_opened");
+}
+
+#[tokio::test]
+async fn sockets_reopen_a_restored_project_before_editing_it() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+
+    let project_id = app.create_project(&owner, &[]).await;
+
+    let mut stale = app.connect_ws(&owner.token).await;
+    stale.open_project(&project_id).await;
+
+    app.post("/delete_project", Some(&owner.token), json!({ "project_id": project_id })).await;
+    stale.recv_type("project_deleted").await;
+    app.post("/restore-project", Some(&owner.token), json!({ "project_id": project_id })).await;
+
+    stale.send(json!({ "action": "add_tier", "request_id": "stale", "tier_id": "s", "label": "S", "color": "#f00" })).await;
+    let error = stale.recv_type("error").await;
+    assert_eq!(error["request_id"], "stale");
+    assert_eq!(error["code"], "no_project_open");
+
+    let mut fresh = app.connect_ws(&owner.token).await;
+    fresh.open_project(&project_id).await;
+    stale.open_project(&project_id).await;
+
+    stale.send(json!({ "action": "add_tier", "tier_id": "s", "label": "S", "color": "#f00" })).await;
+    stale.recv_type("ack").await;
+    assert_eq!(fresh.recv_type("operation_applied").await["op"]["tier_id"], "s");
+}
+
+#[tokio::test]
+async fn invitation_to_a_trashed_project_cannot_be_accepted() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+    let invitee = app.signup_and_login("invitee@example.com").await;
+
+    let project_id = app.create_project(&owner, &[&invitee.email]).await;
+
+    let (_, body) = app.post("/invitations", Some(&invitee.token), json!({})).await;
+    let invitation_id = body["invitations"][0]["invitation_id"].clone();
+
+    app.post("/delete_project", Some(&owner.token), json!({ "project_id": project_id })).await;
+
+    let (status, body) = app.post("/invitations/accept", Some(&invitee.token), json!({ "invitation_id": invitation_id })).await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "{body}");
+
+    app.post("/restore-project", Some(&owner.token), json!({ "project_id": project_id })).await;
+
+    let (_, body) = app.post("/invitations", Some(&invitee.token), json!({})).await;
+    assert_eq!(body["invitations"], json!([]));
+
+    let (status, _) = app.post("/invitations/accept", Some(&invitee.token), json!({ "invitation_id": invitation_id })).await;
+    assert_eq!(status, StatusCode::GONE);
+
+    let mut socket = app.connect_ws(&invitee.token).await;
+    assert_eq!(socket.open_project(&project_id).await["type"], "permission_denied");
+}
+
+#[tokio::test]
+async fn restoring_a_revision_reaches_open_sockets() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+
+    let project_id = app.create_project(&owner, &[]).await;
+
+    let mut editor = app.connect_ws(&owner.token).await;
+    let mut watcher = app.connect_ws(&owner.token).await;
+    editor.open_project(&project_id).await;
+    watcher.open_project(&project_id).await;
+
+    editor.send(json!({
+        "action": "edit_project",
+        "tier_container_html": "<div>broken</div>",
+        "image_carousel_html": "<div>images</div>",
+    })).await;
+    editor.recv_type("ack").await;
+    watcher.recv_type("project_updated").await;
+
+    let (status, body) = app.post("/revisions", Some(&owner.token), json!({ "project_id": project_id })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["revisions"][0]["sequence"], 2);
+    assert_eq!(body["revisions"][1]["sequence"], 1);
+
+    let (status, body) = app.post("/revision", Some(&owner.token), json!({ "project_id": project_id, "sequence": 1 })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["contents"]["tier_container_html"], "<div>tiers</div>");
+
+    let (status, body) = app.post("/restore-revision", Some(&owner.token), json!({ "project_id": project_id, "sequence": 1 })).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["sequence"], 3);
+
+    let update = watcher.recv_type("project_updated").await;
+    assert_eq!(update["contents"]["tier_container_html"], "<div>tiers</div>");
+
+    let (_, body) = app.post("/open-project", Some(&owner.token), json!({ "project_id": project_id })).await;
+    assert_eq!(body["tier_container_html"], "<div>tiers</div>");
+}
+
+#[tokio::test]
+async fn structured_and_legacy_edits_stay_in_step() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+
+    let (status, body) = app.post("/create-project", Some(&owner.token), json!({
+        "project_name": "Project",
+        "template_link": "template",
+        "tier_list": {
+            "tiers": [{ "id": "s", "label": "S", "color": "#ff7f7f", "order": 0, "items": ["cat"] }],
+            "items": [{ "id": "cat", "image": "cat.png", "label": "Cat" }],
+        },
+        "initial_invitations": [],
+    })).await;
+    assert_eq!(status, StatusCode::CREATED, "{body}");
+    let project_id = body["project_id"].clone();
+
+    let (_, body) = app.post("/open-project", Some(&owner.token), json!({ "project_id": project_id })).await;
+    assert!(body["tier_container_html"].as_str().unwrap().contains(r#"data-item-id="cat""#));
+
+    let mut socket = app.connect_ws(&owner.token).await;
+    socket.open_project(&project_id).await;
+
+    socket.send(json!({
+        "action": "edit_project",
+        "tier_container_html": r#"<div class="tier-row" data-tier-id="a"><span class="label">A</span></div>"#,
+        "image_carousel_html": r#"<img src="dog.png" alt="Dog">"#,
+    })).await;
+    let update = socket.recv_type("project_updated").await;
+    assert_eq!(update["contents"]["tier_list"]["tiers"][0]["label"], "A");
+    assert_eq!(update["contents"]["tier_list"]["items"][0]["label"], "Dog");
+
+    socket.send(json!({
+        "action": "edit_project",
+        "request_id": "bad",
+        "tier_list": { "tiers": [], "items": [{ "id": "cat", "image": "cat.png" }], "unplaced": [] },
+    })).await;
+    let error = socket.recv_type("error").await;
+    assert_eq!(error["request_id"], "bad");
+    assert_eq!(error["code"], "invalid_message");
+}
+
+#[tokio::test]
+async fn operations_are_applied_and_relayed_to_peers() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+
+    let project_id = app.create_project(&owner, &[]).await;
+
+    let mut editor = app.connect_ws(&owner.token).await;
+    let mut peer = app.connect_ws(&owner.token).await;
+    editor.open_project(&project_id).await;
+    let mut version = peer.open_project(&project_id).await["version"].as_i64().unwrap();
+
+    for op in [
+        json!({ "action": "add_tier", "tier_id": "s", "label": "S", "color": "#ff7f7f" }),
+        json!({ "action": "add_item", "item_id": "cat", "image": "cat.png", "label": "Cat" }),
+        json!({ "action": "add_item", "item_id": "dog", "image": "dog.png" }),
+        json!({ "action": "move_item", "item_id": "dog", "to_tier": "s", "index": 0 }),
+        json!({ "action": "rename_tier", "tier_id": "s", "label": "Best" }),
+    ] {
+        editor.send(op.clone()).await;
+        editor.recv_type("ack").await;
+
+        let applied = peer.recv_type("operation_applied").await;
+        assert_eq!(applied["op"]["action"], op["action"]);
+        assert_eq!(applied["project_id"], project_id);
+
+        // Every operation gets its own version, even though they coalesce into one revision.
+        assert_eq!(applied["version"].as_i64(), Some(version + 1));
+        version += 1;
+    }
+
+    editor.send(json!({ "action": "move_item", "request_id": "gone", "item_id": "bird", "to_tier": null, "index": 0 })).await;
+    let error = editor.recv_type("error").await;
+    assert_eq!(error["request_id"], "gone");
+    assert_eq!(error["code"], "invalid_message");
+
+    let (_, body) = app.post("/open-project", Some(&owner.token), json!({ "project_id": project_id })).await;
+    assert_eq!(body["tier_list"]["tiers"][0]["label"], "Best");
+    assert_eq!(body["tier_list"]["tiers"][0]["items"], json!(["dog"]));
+    assert_eq!(body["tier_list"]["unplaced"], json!(["cat"]));
+    assert!(body["tier_container_html"].as_str().unwrap().contains(r#"data-item-id="dog""#));
+}
+
+#[tokio::test]
+async fn socket_opening_during_edits_gets_the_snapshot_first() {
+    let app = TestApp::spawn().await;
+    let owner = app.signup_and_login("owner@example.com").await;
+
+    let project_id = app.create_project(&owner, &[]).await;
+
+    let mut editor = app.connect_ws(&owner.token).await;
+    let mut peer = app.connect_ws(&owner.token).await;
+    editor.open_project(&project_id).await;
+
+    // Fewer than the test server's broadcast capacity, so nobody lags into a full resend.
+    const EDITS: usize = 10;
+    for index in 0..EDITS {
+        editor.send(json!({ "action": "add_item", "request_id": index.to_string(), "item_id": format!("item-{index}"), "image": "item.png" })).await;
+    }
+
+    let opened = peer.open_project(&project_id).await;
+    assert_eq!(opened["type"], "project_opened");
+    let mut version = opened["version"].as_i64().unwrap();
+
+    // Each edit reaches its sender as a broadcast before the ack for it.
+    let mut applied_count = 0;
+    let mut acked_count = 0;
+    let mut last_version = 0;
+    while acked_count < EDITS {
+        let message = editor.recv().await;
+
+        if message["type"] == "ack" {
+            assert_eq!(message["request_id"], acked_count.to_string());
+            acked_count += 1;
+            assert!(applied_count >= acked_count, "ack {acked_count} arrived before its broadcast");
+        } else {
+            assert_eq!(message["type"], "operation_applied", "{message}");
+            last_version = message["version"].as_i64().unwrap();
+            applied_count += 1;
+        }
+    }
+
+    // Broadcasts the snapshot already includes may still follow it; the rest pick up right after it.
+    while version < last_version {
+        let applied = peer.recv().await;
+        assert_eq!(applied["type"], "operation_applied", "{applied}");
+
+        let applied_version = applied["version"].as_i64().unwrap();
+        if applied_version > version {
+            assert_eq!(applied_version, version + 1);
+            version = applied_version;
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Email addresses as the server stores and compares them.
+
+/// Trims and lowercases an address, so the same mailbox always maps to the same account and invitations.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Loose sanity check; the verification email is what proves an address works.
+pub fn is_valid_email(email: &str) -> bool {
+    let Some((local, domain)) = email.split_once('@') else {
+        return false;
+    };
+
+    !local.is_empty()
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && domain.contains('.')
+        && !domain.contains('@')
+        && !email.chars().any(char::is_whitespace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_ordinary_addresses() {
+        assert!(is_valid_email("someone@example.com"));
+        assert!(is_valid_email("first.last+tiers@mail.example.org"));
+    }
+
+    #[test]
+    fn rejects_malformed_addresses() {
+        assert!(!is_valid_email(""));
+        assert!(!is_valid_email("no-at-sign.example.com"));
+        assert!(!is_valid_email("@example.com"));
+        assert!(!is_valid_email("someone@localhost"));
+        assert!(!is_valid_email("someone@.example.com"));
+        assert!(!is_valid_email("some one@example.com"));
+        assert!(!is_valid_email("a@b@example.com"));
+    }
+
+    #[test]
+    fn normalizing_ignores_case_and_surrounding_space() {
+        assert_eq!(normalize_email("  Alice@Example.COM "), "alice@example.com");
+    }
+}
@@ -0,0 +1,99 @@
+use crate::email::normalize_email;
+use crate::mailer::Mail;
+use crate::models::{EmailToken, EmailTokenPurpose};
+use crate::token::{expires_after, generate_token, hash_token};
+use crate::error::SharedTierListError::Validation;
+use crate::{error, AppState};
+use axum::extract::State;
+use crate::extract::Json;
+use chrono::Duration;
+use http::StatusCode;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::Deserialize;
+use std::sync::Arc;
+
+const EMAIL_VERIFICATION_LIFETIME_HOURS: i64 = 24;
+
+/// What an account may do before its email address has been verified.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct VerificationPolicy {
+    pub require_for_login: bool,
+    /// Covers sending invitations and joining through invite links. Seeing or answering invitations
+    /// addressed to an email always needs it verified.
+    pub require_for_invitations: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailRequest {
+    token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResendVerificationRequest {
+    email: String,
+}
+
+pub async fn send_verification_email(
+    app_state: &AppState,
+    user_id: ObjectId,
+    email: String,
+) -> error::Result<()> {
+    let token = generate_token();
+
+    app_state.store.insert_email_token(EmailTokenPurpose::EmailVerification, &EmailToken {
+        id: ObjectId::new(),
+        user_id,
+        token_hash: hash_token(&token),
+        created_at: DateTime::now(),
+        expires_at: expires_after(Duration::hours(EMAIL_VERIFICATION_LIFETIME_HOURS)),
+        used: false,
+    }).await?;
+
+    app_state.mailer.send(Mail {
+        to: email,
+        subject: "Verify your Shared Tier Lists email".to_string(),
+        body: format!(
+            "Use this code to verify your email address within {EMAIL_VERIFICATION_LIFETIME_HOURS} hours:\n\n{token}"
+        ),
+    }).await?;
+
+    tracing::debug!("Sent verification email for {user_id}");
+
+    Ok(())
+}
+
+pub async fn verify_email(
+    State(app_state): State<Arc<AppState>>,
+    Json(payload): Json<VerifyEmailRequest>,
+) -> error::Result<StatusCode> {
+    let verification = app_state.store
+        .consume_email_token(EmailTokenPurpose::EmailVerification, &hash_token(&payload.token))
+        .await?
+        .ok_or_else(|| Validation("Verification code is invalid or has expired".to_string()))?;
+
+    let user_id = verification.user_id;
+
+    app_state.store.set_user_verified(user_id).await?;
+
+    tracing::debug!("Verified email for {user_id}");
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn resend_verification_email(
+    State(app_state): State<Arc<AppState>>,
+    Json(payload): Json<ResendVerificationRequest>,
+) -> error::Result<StatusCode> {
+    let user_opt = app_state.store.find_user_by_email(&normalize_email(&payload.email)).await?;
+
+    // Same answer for unknown and already verified accounts so emails cannot be probed.
+    if let Some(user) = user_opt {
+        if !user.verified {
+            send_verification_email(&app_state, user.id, user.email).await?;
+        }
+    }
+
+    Ok(StatusCode::ACCEPTED)
+}
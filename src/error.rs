@@ -1,7 +1,12 @@
+use crate::request_id;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
 use http::StatusCode;
 use mongodb::bson::document::ValueAccessError;
+use serde::Serialize;
 use thiserror::Error;
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Error, Debug)]
 pub enum SharedTierListError {
 
@@ -11,8 +16,154 @@ pub enum SharedTierListError {
     #[error("MongoDB generic error")]
     MongoError(#[from] mongodb::error::Error),
 
+    #[error("BSON serialization failed")]
+    BsonSerializationError(#[from] mongodb::bson::ser::Error),
+
+    #[error("I/O error")]
+    IoError(#[from] std::io::Error),
+
     #[error("{0}")]
     StatusCodeError(StatusCode),
+
+    #[error("{0}")]
+    Validation(String),
+
+    #[error("{0}")]
+    NotFound(String),
+
+    #[error("{0}")]
+    Forbidden(String),
+
+    #[error("{0}")]
+    Conflict(String),
+
+    #[error("{0}")]
+    RateLimited(String),
+}
+
+impl SharedTierListError {
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            SharedTierListError::StatusCodeError(status) => *status,
+            SharedTierListError::Validation(_) => StatusCode::BAD_REQUEST,
+            SharedTierListError::NotFound(_) => StatusCode::NOT_FOUND,
+            SharedTierListError::Forbidden(_) => StatusCode::FORBIDDEN,
+            SharedTierListError::Conflict(_) => StatusCode::CONFLICT,
+            SharedTierListError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Stable identifier clients can match on; the message is for people.
+    pub fn code(&self) -> &'static str {
+        match self.status_code() {
+            StatusCode::BAD_REQUEST => "validation_failed",
+            StatusCode::UNAUTHORIZED => "unauthorized",
+            StatusCode::FORBIDDEN => "forbidden",
+            StatusCode::NOT_FOUND => "not_found",
+            StatusCode::CONFLICT => "conflict",
+            StatusCode::GONE => "gone",
+            StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+            status if status.is_server_error() => "internal_error",
+            _ => "request_failed",
+        }
+    }
+
+    fn message(&self) -> String {
+        match self {
+            SharedTierListError::Validation(message)
+            | SharedTierListError::NotFound(message)
+            | SharedTierListError::Forbidden(message)
+            | SharedTierListError::Conflict(message)
+            | SharedTierListError::RateLimited(message) => message.clone(),
+            SharedTierListError::StatusCodeError(status) if !status.is_server_error() => {
+                status.canonical_reason().unwrap_or("Request failed").to_string()
+            }
+            // Database and I/O details stay in the logs.
+            _ => "Internal server error".to_string(),
+        }
+    }
+}
+
+impl From<StatusCode> for SharedTierListError {
+    fn from(status: StatusCode) -> Self {
+        SharedTierListError::StatusCodeError(status)
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    code: &'static str,
+    message: String,
+    request_id: Option<String>,
+}
+
+impl IntoResponse for SharedTierListError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        let request_id = request_id::current();
+
+        if status.is_server_error() {
+            tracing::error!("Request {} failed: {self:?}", request_id.as_deref().unwrap_or("-"));
+        }
+
+        let body = ErrorResponse {
+            code: self.code(),
+            message: self.message(),
+            request_id,
+        };
+
+        (status, Json(body)).into_response()
+    }
 }
 
 pub type Result<T> = std::result::Result<T, SharedTierListError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variants_map_to_their_status_and_code() {
+        let cases = [
+            (SharedTierListError::Validation("bad".into()), StatusCode::BAD_REQUEST, "validation_failed"),
+            (SharedTierListError::NotFound("gone".into()), StatusCode::NOT_FOUND, "not_found"),
+            (SharedTierListError::Forbidden("no".into()), StatusCode::FORBIDDEN, "forbidden"),
+            (SharedTierListError::Conflict("taken".into()), StatusCode::CONFLICT, "conflict"),
+            (SharedTierListError::RateLimited("slow".into()), StatusCode::TOO_MANY_REQUESTS, "rate_limited"),
+            (StatusCode::UNAUTHORIZED.into(), StatusCode::UNAUTHORIZED, "unauthorized"),
+            (StatusCode::GONE.into(), StatusCode::GONE, "gone"),
+        ];
+
+        for (error, status, code) in cases {
+            assert_eq!(error.status_code(), status);
+            assert_eq!(error.code(), code);
+        }
+    }
+
+    #[test]
+    fn internal_details_are_not_exposed() {
+        let error = SharedTierListError::IoError(std::io::Error::other("disk on fire"));
+
+        assert_eq!(error.code(), "internal_error");
+        assert_eq!(error.message(), "Internal server error");
+    }
+
+    #[tokio::test]
+    async fn response_body_carries_code_message_and_request_id() {
+        let response = request_id::scope("req-1".to_string(), async {
+            SharedTierListError::Conflict("Email already registered".into()).into_response()
+        }).await;
+
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(body, serde_json::json!({
+            "code": "conflict",
+            "message": "Email already registered",
+            "request_id": "req-1",
+        }));
+    }
+}
@@ -0,0 +1,60 @@
+//! Extractors whose rejections are `SharedTierListError`s, so malformed requests get the same error
+//! body as every other failure instead of axum's plain-text one.
+
+use crate::error::SharedTierListError;
+use axum::extract::rejection::{JsonRejection, PathRejection};
+use axum::extract::{FromRequest, FromRequestParts, Request};
+use axum::response::{IntoResponse, Response};
+use http::request::Parts;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// `axum::Json`, rejecting with `Validation`.
+pub struct Json<T>(pub T);
+
+impl<T, S> FromRequest<S> for Json<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = SharedTierListError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::Json(value) = axum::Json::<T>::from_request(request, state).await?;
+        Ok(Json(value))
+    }
+}
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+/// `axum::extract::Path`, rejecting with `Validation`.
+pub struct Path<T>(pub T);
+
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = SharedTierListError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(value) = axum::extract::Path::<T>::from_request_parts(parts, state).await?;
+        Ok(Path(value))
+    }
+}
+
+impl From<JsonRejection> for SharedTierListError {
+    fn from(rejection: JsonRejection) -> Self {
+        SharedTierListError::Validation(rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for SharedTierListError {
+    fn from(rejection: PathRejection) -> Self {
+        SharedTierListError::Validation(rejection.body_text())
+    }
+}
@@ -1,80 +1,355 @@
-use crate::authentication::authenticate_user;
-use crate::db_constants::{Collections, ProjectFields, UserFields};
-use crate::AppState;
+use crate::authentication::AuthenticatedUser;
+use crate::authorization::{authorize_member_management, project_role};
+use crate::email::{is_valid_email, normalize_email};
+use crate::error::SharedTierListError::{Forbidden, NotFound, RateLimited, StatusCodeError};
+use crate::models::{Invitation, InvitationStatus};
+use crate::token::expires_after;
+use crate::{error, AppState};
 use axum::extract::State;
-use axum::Json;
-use axum_extra::TypedHeader;
-use futures_util::TryStreamExt;
-use headers::authorization::Bearer;
-use headers::Authorization;
+use crate::extract::Json;
+use chrono::Duration;
 use http::StatusCode;
 use mongodb::bson::oid::ObjectId;
-use mongodb::bson::{doc, Document};
-use mongodb::Database;
-use serde::Deserialize;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tracing::debug;
 
+const INVITATION_LIFETIME_DAYS: i64 = 14;
+const MAX_INVITATIONS_PER_HOUR: u64 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InviteOutcome {
+    Invited,
+    AlreadyMember,
+    /// No account uses this email yet; the invitation waits for a signup.
+    NoSuchUser,
+    InvalidAddress,
+    RateLimited,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InviteResult {
+    email: String,
+    outcome: InviteOutcome,
+}
+
+#[derive(Serialize)]
+pub struct InviteResponse {
+    pub(crate) results: Vec<InviteResult>,
+}
+
 #[derive(Deserialize)]
 pub struct InviteRequest {
-    user_id: ObjectId,
     project_id: ObjectId,
     emails: Vec<String>
 }
 
-pub async fn invite_users(db: Database, project_id: ObjectId, emails: Vec<String>) -> crate::error::Result<()> {
-    let users = db.collection::<Document>(Collections::USERS);
-    let projects = db.collection::<Document>(Collections::PROJECTS);
-    
-    let filter = doc! { UserFields::EMAIL: { "$in": emails } };
-    let mut cursor = users.find(filter).await?;
-    let mut invited_user_ids = Vec::new();
-    
-    while let Some(user) = cursor.try_next().await? {
-        let user_id = user.get_object_id(UserFields::ID)?;
+#[derive(Deserialize)]
+pub struct PendingInvitationsRequest {
+    project_id: ObjectId,
+}
 
-        users.update_one(
-            doc! { UserFields::ID: user_id },
-            doc! { "$addToSet": { UserFields::PROJECTS: project_id } }
-        ).await?;
+#[derive(Deserialize)]
+pub struct CancelPendingInvitationRequest {
+    project_id: ObjectId,
+    email: String,
+}
 
-        invited_user_ids.push(user_id);
-    }
+#[derive(Deserialize)]
+pub struct RespondToInvitationRequest {
+    invitation_id: ObjectId,
+}
+
+#[derive(Serialize)]
+pub struct PendingInvitationsResponse {
+    invitations: Vec<PendingInvitation>,
+}
+
+#[derive(Serialize)]
+struct PendingInvitation {
+    email: String,
+    invited_by: ObjectId,
+    created_at: String,
+    expires_at: String,
+}
+
+#[derive(Serialize)]
+pub struct IncomingInvitationsResponse {
+    invitations: Vec<IncomingInvitation>,
+}
+
+#[derive(Serialize)]
+struct IncomingInvitation {
+    invitation_id: ObjectId,
+    project_id: ObjectId,
+    project_name: String,
+    invited_by: ObjectId,
+    created_at: String,
+    expires_at: String,
+}
+
+pub async fn add_project_members(
+    app_state: &AppState,
+    project_id: ObjectId,
+    user_ids: &[ObjectId],
+) -> error::Result<()> {
+    app_state.store.add_project_to_users(user_ids, project_id).await?;
 
     debug!("Updated user projects");
 
-    projects.update_one(
-        doc! { ProjectFields::ID: project_id },
-        doc! { "$addToSet": { ProjectFields::CONTRIBUTORS: { "$each": invited_user_ids } } }
-    ).await?;
+    app_state.store.add_contributors(project_id, user_ids).await?;
 
     Ok(())
 }
 
-// todo: add payload to response for failed invitations
+async fn recent_invitation_count(app_state: &AppState, inviter_id: ObjectId) -> error::Result<u64> {
+    app_state.store.count_invitations_since(inviter_id, expires_after(-Duration::hours(1))).await
+}
+
+/// Records a pending invitation for every eligible email; nobody joins until they accept.
+pub async fn invite_users(
+    app_state: &AppState,
+    project_id: ObjectId,
+    inviter_id: ObjectId,
+    emails: Vec<String>,
+) -> error::Result<Vec<InviteResult>> {
+    let project = app_state.store.find_project(project_id)
+        .await?
+        .filter(|project| !project.is_trashed())
+        .ok_or_else(|| NotFound("Project not found".to_string()))?;
+
+    // A pending invitation past its expiry would otherwise hold the unique slot a fresh one needs.
+    app_state.store.expire_invitations().await?;
+
+    let mut sent = recent_invitation_count(app_state, inviter_id).await?;
+    let mut results: Vec<InviteResult> = vec![];
+
+    for email in emails.iter().map(|email| normalize_email(email)) {
+        if results.iter().any(|result| result.email == email) {
+            continue;
+        }
+
+        if !is_valid_email(&email) {
+            results.push(InviteResult { email, outcome: InviteOutcome::InvalidAddress });
+            continue;
+        }
+
+        let user_opt = app_state.store.find_user_by_email(&email).await?;
+
+        if let Some(user) = &user_opt {
+            if project_role(&project, user.id).is_some() {
+                results.push(InviteResult { email, outcome: InviteOutcome::AlreadyMember });
+                continue;
+            }
+        }
+
+        if sent >= MAX_INVITATIONS_PER_HOUR {
+            results.push(InviteResult { email, outcome: InviteOutcome::RateLimited });
+            continue;
+        }
+
+        // Re-inviting keeps the open invitation instead of stacking duplicates.
+        let created = app_state.store.create_invitation(&Invitation {
+            id: ObjectId::new(),
+            email: email.clone(),
+            project_id,
+            invited_by: inviter_id,
+            status: InvitationStatus::Pending,
+            created_at: DateTime::now(),
+            expires_at: expires_after(Duration::days(INVITATION_LIFETIME_DAYS)),
+            responded_at: None,
+        }).await?;
+
+        if created {
+            sent += 1;
+        }
+
+        let outcome = match user_opt {
+            Some(_) => InviteOutcome::Invited,
+            None => InviteOutcome::NoSuchUser,
+        };
+
+        results.push(InviteResult { email, outcome });
+    }
+
+    debug!("Invited users to {project_id}");
+
+    Ok(results)
+}
+
 pub async fn invite_to_project(
     State(app_state): State<Arc<AppState>>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    AuthenticatedUser { user_id, verified, .. }: AuthenticatedUser,
     Json(payload): Json<InviteRequest>,
-) -> Result<StatusCode, StatusCode> {
-    authenticate_user(app_state.clone(), auth).await
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
-
-    let projects = app_state.db.collection::<Document>(Collections::PROJECTS);
-
-    let project_opt = projects
-        .find_one(doc! { ProjectFields::ID: payload.project_id })
-        .await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    if let Some(project) = project_opt {
-        let project_id = project.get_object_id(ProjectFields::ID)
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-        
-        match invite_users(app_state.db.clone(), project_id, payload.emails).await {
-            Ok(()) => Ok(StatusCode::OK),
-            Err(_) => Ok(StatusCode::INTERNAL_SERVER_ERROR)
-        }
+) -> error::Result<Json<InviteResponse>> {
+    authorize_member_management(app_state.store.as_ref(), user_id, payload.project_id).await?;
+
+    if app_state.verification_policy.require_for_invitations && !verified {
+        return Err(Forbidden("Verify your email address before inviting people".to_string()));
+    }
+
+    // Individual addresses past the limit are reported per email; this only stops a sender who is already out.
+    if recent_invitation_count(&app_state, user_id).await? >= MAX_INVITATIONS_PER_HOUR {
+        return Err(RateLimited(format!("You can send at most {MAX_INVITATIONS_PER_HOUR} invitations per hour")));
+    }
+
+    let results = invite_users(&app_state, payload.project_id, user_id, payload.emails).await?;
+
+    Ok(Json(InviteResponse {
+        results
+    }))
+}
+
+pub async fn list_pending_invitations(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Json(payload): Json<PendingInvitationsRequest>,
+) -> error::Result<Json<PendingInvitationsResponse>> {
+    authorize_member_management(app_state.store.as_ref(), user_id, payload.project_id).await?;
+
+    app_state.store.expire_invitations().await?;
+
+    let pending = app_state.store.pending_invitations_for_project(payload.project_id)
+        .await?
+        .into_iter()
+        .map(|invitation| PendingInvitation {
+            email: invitation.email,
+            invited_by: invitation.invited_by,
+            created_at: invitation.created_at.to_string(),
+            expires_at: invitation.expires_at.to_string(),
+        })
+        .collect();
+
+    Ok(Json(PendingInvitationsResponse {
+        invitations: pending
+    }))
+}
+
+pub async fn cancel_pending_invitation(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Json(payload): Json<CancelPendingInvitationRequest>,
+) -> error::Result<StatusCode> {
+    authorize_member_management(app_state.store.as_ref(), user_id, payload.project_id).await?;
+
+    let deleted = app_state.store.delete_pending_invitations(payload.project_id, &normalize_email(&payload.email)).await?;
+
+    if deleted == 0 {
+        Err(NotFound("No pending invitation for that email".to_string()))
+    } else {
+        Ok(StatusCode::NO_CONTENT)
+    }
+}
+
+/// Invitations are addressed to an email, so only an account that proved it owns the address may see or
+/// answer them, whatever the verification policy says about sending them.
+fn require_verified_email(verified: bool) -> error::Result<()> {
+    if verified {
+        Ok(())
+    } else {
+        Err(Forbidden("Verify your email address to see the invitations sent to it".to_string()))
+    }
+}
+
+pub async fn list_incoming_invitations(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { email, verified, .. }: AuthenticatedUser,
+) -> error::Result<Json<IncomingInvitationsResponse>> {
+    require_verified_email(verified)?;
+
+    app_state.store.expire_invitations().await?;
+
+    let invitations = app_state.store.pending_invitations_for_email(&email).await?;
+
+    let mut incoming = vec![];
+    for invitation in invitations {
+        // Invitations to projects that no longer exist or sit in the trash are simply not shown.
+        let project_opt = app_state.store.find_project(invitation.project_id).await?;
+        let Some(project) = project_opt.filter(|project| !project.is_trashed()) else {
+            continue;
+        };
+
+        incoming.push(IncomingInvitation {
+            invitation_id: invitation.id,
+            project_id: invitation.project_id,
+            project_name: project.name,
+            invited_by: invitation.invited_by,
+            created_at: invitation.created_at.to_string(),
+            expires_at: invitation.expires_at.to_string(),
+        });
+    }
+
+    Ok(Json(IncomingInvitationsResponse {
+        invitations: incoming
+    }))
+}
+
+/// Moves one of the caller's pending invitations to `status`, failing with `GONE` once it expired.
+async fn respond_to_invitation(
+    app_state: &AppState,
+    email: &str,
+    invitation_id: ObjectId,
+    status: InvitationStatus,
+) -> error::Result<Invitation> {
+    let invitation_opt = app_state.store.respond_to_invitation(invitation_id, email, status).await?;
+
+    if let Some(invitation) = invitation_opt {
+        return Ok(invitation);
+    }
+
+    app_state.store.expire_invitations().await?;
+
+    let expired = app_state.store.find_invitation(invitation_id)
+        .await?
+        .is_some_and(|invitation| invitation.email == email && invitation.status == InvitationStatus::Expired);
+
+    if expired {
+        Err(StatusCodeError(StatusCode::GONE))
     } else {
-        Err(StatusCode::NOT_FOUND)
+        Err(NotFound("Invitation not found".to_string()))
     }
 }
+
+pub async fn accept_invitation(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { user_id, email, verified, .. }: AuthenticatedUser,
+    Json(payload): Json<RespondToInvitationRequest>,
+) -> error::Result<StatusCode> {
+    require_verified_email(verified)?;
+
+    let invitation_opt = app_state.store.find_invitation(payload.invitation_id).await?;
+
+    if let Some(invitation) = invitation_opt.filter(|invitation| invitation.email == email) {
+        let project_opt = app_state.store.find_project(invitation.project_id).await?;
+
+        // A trashed or purged project cannot be joined, and never will be again.
+        if project_opt.is_none_or(|project| project.is_trashed()) {
+            app_state.store.respond_to_invitation(invitation.id, &email, InvitationStatus::Expired).await?;
+            return Err(NotFound("Project not found".to_string()));
+        }
+    }
+
+    let invitation = respond_to_invitation(&app_state, &email, payload.invitation_id, InvitationStatus::Accepted).await?;
+
+    let project_id = invitation.project_id;
+
+    add_project_members(&app_state, project_id, &[user_id]).await?;
+
+    debug!("{user_id} joined {project_id}");
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn decline_invitation(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { email, verified, .. }: AuthenticatedUser,
+    Json(payload): Json<RespondToInvitationRequest>,
+) -> error::Result<StatusCode> {
+    require_verified_email(verified)?;
+
+    respond_to_invitation(&app_state, &email, payload.invitation_id, InvitationStatus::Declined).await?;
+
+    Ok(StatusCode::OK)
+}
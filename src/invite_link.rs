@@ -0,0 +1,192 @@
+use crate::authentication::AuthenticatedUser;
+use crate::authorization::{authorize_member_management, project_role, ProjectRole};
+use crate::models;
+use crate::token::{expires_after, generate_token, hash_token};
+use crate::error::SharedTierListError::{Forbidden, NotFound, StatusCodeError, Validation};
+use crate::{error, AppState};
+use crate::extract::{Json, Path};
+use axum::extract::State;
+use chrono::Duration;
+use http::StatusCode;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct CreateInviteLinkRequest {
+    project_id: ObjectId,
+    role: ProjectRole,
+    expires_in_hours: Option<i64>,
+    max_uses: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct CreateInviteLinkResponse {
+    link_id: ObjectId,
+    token: String,
+}
+
+#[derive(Deserialize)]
+pub struct InviteLinksRequest {
+    project_id: ObjectId,
+}
+
+#[derive(Serialize)]
+pub struct InviteLinksResponse {
+    links: Vec<InviteLink>,
+}
+
+#[derive(Serialize)]
+struct InviteLink {
+    link_id: ObjectId,
+    role: ProjectRole,
+    created_at: String,
+    expires_at: Option<String>,
+    max_uses: Option<i64>,
+    uses: i64,
+}
+
+#[derive(Deserialize)]
+pub struct RevokeInviteLinkRequest {
+    project_id: ObjectId,
+    link_id: ObjectId,
+}
+
+#[derive(Serialize)]
+pub struct JoinProjectResponse {
+    project_id: ObjectId,
+    role: ProjectRole,
+}
+
+pub async fn create_invite_link(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Json(payload): Json<CreateInviteLinkRequest>,
+) -> error::Result<Json<CreateInviteLinkResponse>> {
+    authorize_member_management(app_state.store.as_ref(), user_id, payload.project_id).await?;
+
+    if payload.role == ProjectRole::Owner {
+        return Err(Validation("Invite links cannot grant the owner role".to_string()));
+    }
+
+    let invalid_limits = payload.expires_in_hours.is_some_and(|hours| hours <= 0)
+        || payload.max_uses.is_some_and(|max_uses| max_uses <= 0);
+
+    if invalid_limits {
+        return Err(Validation("Expiry and use limits must be positive".to_string()));
+    }
+
+    let token = generate_token();
+    let expires_at = payload.expires_in_hours.map(|hours| expires_after(Duration::hours(hours)));
+
+    let link_id = ObjectId::new();
+
+    app_state.store.insert_invite_link(&models::InviteLink {
+        id: link_id,
+        project_id: payload.project_id,
+        created_by: user_id,
+        token_hash: hash_token(&token),
+        role: payload.role,
+        created_at: DateTime::now(),
+        expires_at,
+        max_uses: payload.max_uses,
+        uses: 0,
+        revoked: false,
+    }).await?;
+
+    tracing::debug!("Created invite link {link_id} for {}", payload.project_id);
+
+    Ok(Json(CreateInviteLinkResponse {
+        link_id,
+        token,
+    }))
+}
+
+pub async fn list_invite_links(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Json(payload): Json<InviteLinksRequest>,
+) -> error::Result<Json<InviteLinksResponse>> {
+    authorize_member_management(app_state.store.as_ref(), user_id, payload.project_id).await?;
+
+    let links = app_state.store.usable_invite_links(payload.project_id)
+        .await?
+        .into_iter()
+        .map(|link| InviteLink {
+            link_id: link.id,
+            role: link.role,
+            created_at: link.created_at.to_string(),
+            expires_at: link.expires_at.map(|expires_at| expires_at.to_string()),
+            max_uses: link.max_uses,
+            uses: link.uses,
+        })
+        .collect();
+
+    Ok(Json(InviteLinksResponse {
+        links
+    }))
+}
+
+pub async fn revoke_invite_link(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Json(payload): Json<RevokeInviteLinkRequest>,
+) -> error::Result<StatusCode> {
+    authorize_member_management(app_state.store.as_ref(), user_id, payload.project_id).await?;
+
+    let revoked = app_state.store.revoke_invite_link(payload.project_id, payload.link_id).await?;
+
+    if !revoked {
+        Err(NotFound("Invite link not found".to_string()))
+    } else {
+        Ok(StatusCode::NO_CONTENT)
+    }
+}
+
+pub async fn join_project(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { user_id, verified, .. }: AuthenticatedUser,
+    Path(token): Path<String>,
+) -> error::Result<Json<JoinProjectResponse>> {
+    if app_state.verification_policy.require_for_invitations && !verified {
+        return Err(Forbidden("Verify your email address before joining projects".to_string()));
+    }
+
+    let token_hash = hash_token(&token);
+
+    let link = app_state.store.find_usable_invite_link(&token_hash).await?
+        .ok_or_else(|| NotFound("Invite link is invalid or no longer usable".to_string()))?;
+
+    let project_id = link.project_id;
+
+    let project = app_state.store.find_project(project_id)
+        .await?
+        .filter(|project| !project.is_trashed())
+        .ok_or_else(|| NotFound("Project not found".to_string()))?;
+
+    // Existing members keep their role and do not use up the link.
+    if let Some(role) = project_role(&project, user_id) {
+        return Ok(Json(JoinProjectResponse {
+            project_id,
+            role,
+        }));
+    }
+
+    // Counting the use in the same operation that re-checks the limits keeps max_uses exact.
+    let link = app_state.store.use_invite_link(&token_hash).await?
+        .ok_or(StatusCodeError(StatusCode::GONE))?;
+
+    let role = link.role;
+
+    // One write, so the new member is never briefly treated as an editor and no role outlives a failed join.
+    app_state.store.add_member_with_role(project_id, user_id, role).await?;
+    app_state.store.add_project_to_users(&[user_id], project_id).await?;
+
+    tracing::debug!("{user_id} joined {project_id} through a link");
+
+    Ok(Json(JoinProjectResponse {
+        project_id,
+        role,
+    }))
+}
@@ -0,0 +1,165 @@
+mod error;
+mod extract;
+mod project_options;
+mod open_project_list;
+mod ws;
+mod db_constants;
+mod authentication;
+mod authorization;
+mod invite;
+mod refresh_token;
+mod session;
+mod token;
+pub mod mailer;
+mod password_reset;
+mod email;
+mod email_verification;
+mod members;
+mod invite_link;
+mod ws_types;
+mod request_id;
+mod trash;
+mod revisions;
+mod tier_list;
+pub mod models;
+pub mod store;
+pub mod config;
+#[cfg(test)]
+mod test_fixtures;
+#[cfg(test)]
+mod test_harness;
+#[cfg(test)]
+mod e2e_tests;
+
+use std::collections::HashMap;
+use crate::open_project_list::open_project_list;
+use std::sync::Arc;
+use chrono::Duration;
+use axum::{middleware, Router};
+use axum::routing::{any, post};
+use mongodb::bson::oid::ObjectId;
+use tokio::sync::{broadcast, Mutex};
+use tokio::sync::broadcast::Sender;
+use crate::project_options::{create_project, delete_project, open_project};
+
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use crate::authentication::{login, signup};
+use crate::invite::{
+    accept_invitation, cancel_pending_invitation, decline_invitation, invite_to_project,
+    list_incoming_invitations, list_pending_invitations,
+};
+use crate::members::set_member_role;
+use crate::invite_link::{create_invite_link, join_project, list_invite_links, revoke_invite_link};
+use crate::refresh_token::{logout, refresh};
+use crate::session::{list_sessions, revoke_all_sessions, revoke_session_handler};
+use crate::mailer::{FileMailer, LogMailer, Mailer};
+use crate::password_reset::{confirm_password_reset, request_password_reset};
+use crate::email_verification::{resend_verification_email, verify_email, VerificationPolicy};
+use crate::config::Config;
+use crate::ws::{ws_handler, LiveSession};
+use crate::store::Store;
+use crate::request_id::{assign_request_id, REQUEST_ID_HEADER};
+use http::{HeaderName, HeaderValue};
+use crate::trash::{list_trashed_projects, restore_project, spawn_trash_purge};
+use crate::tier_list::migrate_legacy_tier_lists;
+use crate::revisions::{get_revision, list_revisions, restore_revision};
+
+const SESSION_REVOCATION_CHANNEL_CAPACITY: usize = 64;
+
+struct AppState {
+    store: Arc<dyn Store>,
+    jwt_secret_key: String,
+    live_sessions: Mutex<HashMap<ObjectId, LiveSession>>,
+    session_revocations: Sender<ObjectId>,
+    mailer: Arc<dyn Mailer>,
+    verification_policy: VerificationPolicy,
+    access_token_lifetime: Duration,
+    refresh_token_lifetime: Duration,
+    broadcast_channel_capacity: usize,
+}
+
+/// Builds the server for `config` on top of `store`, ready to hand to `axum::serve`.
+///
+/// Also brings the stored data up to date and starts the background jobs, so the server is fully
+/// running once this returns. `config` is expected to have passed `Config::validate`.
+pub async fn build_app(config: &Config, store: Arc<dyn Store>) -> error::Result<Router> {
+    let migrated = migrate_legacy_tier_lists(store.as_ref()).await?;
+    if migrated > 0 {
+        tracing::info!("Parsed tier lists for {migrated} legacy projects");
+    }
+
+    spawn_trash_purge(config, store.clone());
+
+    let mailer: Arc<dyn Mailer> = match &config.mail_outbox_dir {
+        Some(outbox_dir) => Arc::new(FileMailer::new(outbox_dir.clone())),
+        None => Arc::new(LogMailer),
+    };
+
+    let app_state = AppState {
+        store,
+        live_sessions: Mutex::new(HashMap::new()),
+        session_revocations: broadcast::channel(SESSION_REVOCATION_CHANNEL_CAPACITY).0,
+        mailer,
+        verification_policy: config.verification,
+        jwt_secret_key: config.jwt_secret_key.clone(),
+        access_token_lifetime: Duration::minutes(config.access_token_lifetime_minutes),
+        refresh_token_lifetime: Duration::days(config.refresh_token_lifetime_days),
+        broadcast_channel_capacity: config.broadcast_channel_capacity,
+    };
+
+    Ok(app(app_state, cors_layer(&config.cors_allowed_origins)))
+}
+
+fn cors_layer(allowed_origins: &[String]) -> CorsLayer {
+    let allow_origin = if allowed_origins.is_empty() {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(allowed_origins.iter().filter_map(|origin| HeaderValue::from_str(origin).ok()))
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(Any)
+        .allow_headers(Any)
+        .expose_headers([HeaderName::from_static(REQUEST_ID_HEADER)])
+}
+
+/// The full route table with its middleware, ready to serve.
+fn app(app_state: AppState, cors: CorsLayer) -> Router {
+    Router::new()
+        .route("/signup", post(signup))
+        .route("/login", post(login))
+        .route("/refresh", post(refresh))
+        .route("/logout", post(logout))
+        .route("/verify-email", post(verify_email))
+        .route("/verify-email/resend", post(resend_verification_email))
+        .route("/password-reset/request", post(request_password_reset))
+        .route("/password-reset/confirm", post(confirm_password_reset))
+        .route("/sessions", post(list_sessions))
+        .route("/revoke-session", post(revoke_session_handler))
+        .route("/revoke-all-sessions", post(revoke_all_sessions))
+        .route("/open-project-list", post(open_project_list))
+        .route("/create-project", post(create_project))
+        .route("/open-project", post(open_project))
+        .route("/delete_project", post(delete_project))
+        .route("/trashed-projects", post(list_trashed_projects))
+        .route("/restore-project", post(restore_project))
+        .route("/revisions", post(list_revisions))
+        .route("/revision", post(get_revision))
+        .route("/restore-revision", post(restore_revision))
+        .route("/invite-to-project", post(invite_to_project))
+        .route("/pending-invitations", post(list_pending_invitations))
+        .route("/cancel-pending-invitation", post(cancel_pending_invitation))
+        .route("/invitations", post(list_incoming_invitations))
+        .route("/invitations/accept", post(accept_invitation))
+        .route("/invitations/decline", post(decline_invitation))
+        .route("/create-invite-link", post(create_invite_link))
+        .route("/invite-links", post(list_invite_links))
+        .route("/revoke-invite-link", post(revoke_invite_link))
+        .route("/join/{token}", post(join_project))
+        .route("/set-member-role", post(set_member_role))
+        .route("/ws", any(ws_handler))
+        .layer(middleware::from_fn(assign_request_id))
+        .layer(cors)
+        .with_state(Arc::new(app_state))
+}
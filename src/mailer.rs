@@ -0,0 +1,77 @@
+use crate::error;
+use async_trait::async_trait;
+use mongodb::bson::oid::ObjectId;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers account emails; swapped for a real provider outside local development.
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, mail: Mail) -> error::Result<()>;
+}
+
+/// Writes every mail to the log instead of delivering it.
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, mail: Mail) -> error::Result<()> {
+        tracing::info!("Mail to {}: {}\n{}", mail.to, mail.subject, mail.body);
+        Ok(())
+    }
+}
+
+/// Drops every mail into a directory as a text file, one file per message.
+pub struct FileMailer {
+    outbox_dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(outbox_dir: PathBuf) -> FileMailer {
+        FileMailer { outbox_dir }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, mail: Mail) -> error::Result<()> {
+        tokio::fs::create_dir_all(&self.outbox_dir).await?;
+
+        // Addresses may hold path separators, so the recipient only goes in the header, never in the path.
+        let file_name = format!("{}-{}.txt", chrono::Utc::now().format("%Y%m%dT%H%M%S%.f"), ObjectId::new());
+        let contents = format!("To: {}\nSubject: {}\n\n{}\n", mail.to, mail.subject, mail.body);
+
+        tokio::fs::write(self.outbox_dir.join(file_name), contents).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn recipient_stays_out_of_the_path() {
+        let root = std::env::temp_dir().join(format!("shared-tier-list-mailer-{}", ObjectId::new()));
+        let mailer = FileMailer::new(root.join("outbox"));
+
+        mailer.send(Mail {
+            to: "x@y.z/../../escaped".to_string(),
+            subject: "Subject".to_string(),
+            body: "Body".to_string(),
+        }).await.unwrap();
+
+        let outbox: Vec<_> = std::fs::read_dir(root.join("outbox")).unwrap().collect();
+        let escaped = root.join("escaped").exists() || std::fs::read_dir(&root).unwrap().count() != 1;
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(outbox.len(), 1);
+        assert!(!escaped);
+    }
+}
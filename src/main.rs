@@ -1,87 +1,44 @@
-mod error;
-mod project_options;
-mod open_project_list;
-mod ws;
-mod db_constants;
-mod authentication;
-mod invite;
-mod ws_types;
-
-use std::collections::HashMap;
-use crate::open_project_list::open_project_list;
 use dotenv::dotenv;
-use mongodb::{options::ClientOptions, Client};
-use std::env;
-use std::sync::Arc;
-use axum::Router;
-use axum::routing::{any, post};
-use mongodb::bson::oid::ObjectId;
-use serde::{Deserialize, Serialize};
-use tokio::sync::{broadcast, Mutex};
-use tokio::sync::broadcast::Sender;
-use crate::project_options::{create_project, delete_project, open_project};
-
-use tower_http::cors::{Any, CorsLayer};
+use shared_tier_list::build_app;
+use shared_tier_list::config::Config;
+use shared_tier_list::store;
+use std::process::ExitCode;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
-use crate::authentication::{login, signup};
-use crate::invite::invite_to_project;
-use crate::ws::ws_handler;
-use crate::ws_types::ProjectContentsResponse;
-
-// #[derive(Debug, Serialize, Deserialize, Clone)]
-// pub struct ProjectContents {
-//     pub tier_container_html: String,
-//     pub image_carousel_html: String,
-// }
-
-struct AppState {
-    db: mongodb::Database,
-    jwt_secret_key: String,
-    live_sessions: Mutex<HashMap<ObjectId, Sender<ProjectContentsResponse>>>,
-}
 
 #[tokio::main]
-async fn main() -> mongodb::error::Result<()> {
+async fn main() -> ExitCode {
     dotenv().ok();
 
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| format!("{}=trace", env!("CARGO_CRATE_NAME")).into()),
+                .unwrap_or_else(|_| "shared_tier_list=trace".into()),
         )
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    let uri = env::var("MONGODB_URI").expect("Error: No MONGODB_URI");
-    let client_options = ClientOptions::parse(uri).await?;
-    let client = Client::with_options(client_options)?;
-    let db = client.database("shared_tier_lists");
-
-    let app_state = AppState {
-        db,
-        live_sessions: Mutex::new(HashMap::new()),
-        jwt_secret_key: env::var("JWT_SECRET_KEY").expect("Error: No JWT_SECRET_KEY"),
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("Invalid configuration: {e}");
+            return ExitCode::FAILURE;
+        }
     };
 
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+    if let Err(e) = serve(config).await {
+        tracing::error!("{e}");
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
 
-    let app = Router::new()
-        .route("/signup", post(signup))
-        .route("/login", post(login))
-        .route("/open-project-list", post(open_project_list))
-        .route("/create-project", post(create_project))
-        .route("/delete_project", post(delete_project))
-        .route("/invite-to-project", post(invite_to_project))
-        .route("/ws", any(ws_handler))
-        .layer(cors)
-        .with_state(Arc::new(app_state));
-    
+async fn serve(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let store = store::open(&config).await?;
+    let app = build_app(&config, store).await?;
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
+    let listener = tokio::net::TcpListener::bind(config.bind_address).await?;
     tracing::debug!("listening on {}", listener.local_addr()?);
     axum::serve(listener, app).await?;
 
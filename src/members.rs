@@ -0,0 +1,42 @@
+use crate::authentication::AuthenticatedUser;
+use crate::authorization::{authorize_member_management, project_role, ProjectRole};
+use crate::error::SharedTierListError::{NotFound, Validation};
+use crate::{error, AppState};
+use axum::extract::State;
+use crate::extract::Json;
+use http::StatusCode;
+use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct SetMemberRoleRequest {
+    project_id: ObjectId,
+    member_id: ObjectId,
+    role: ProjectRole,
+}
+
+pub async fn set_member_role(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Json(payload): Json<SetMemberRoleRequest>,
+) -> error::Result<StatusCode> {
+    let project = authorize_member_management(app_state.store.as_ref(), user_id, payload.project_id).await?;
+
+    // Ownership lives in the `owner` field and is not transferable through roles.
+    if payload.role == ProjectRole::Owner {
+        return Err(Validation("Members cannot be given the owner role".to_string()));
+    }
+
+    match project_role(&project, payload.member_id) {
+        None => return Err(NotFound("Member not found".to_string())),
+        Some(ProjectRole::Owner) => return Err(Validation("The owner's role cannot be changed".to_string())),
+        Some(_) => {}
+    }
+
+    app_state.store.set_member_role(payload.project_id, payload.member_id, payload.role).await?;
+
+    tracing::debug!("Set role of {} to {}", payload.member_id, payload.role.as_str());
+
+    Ok(StatusCode::OK)
+}
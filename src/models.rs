@@ -0,0 +1,299 @@
+use crate::authorization::ProjectRole;
+use crate::tier_list::{parse_tier_list, TierList};
+use crate::ws_types::ProjectContentsResponse;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A document in the `users` collection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub email: String,
+    pub display_name: String,
+    pub passwd_hash: String,
+    /// Accounts created before verification existed have no flag and count as verified.
+    #[serde(default = "verified_by_default")]
+    pub verified: bool,
+    #[serde(default)]
+    pub projects: Vec<ObjectId>,
+}
+
+fn verified_by_default() -> bool {
+    true
+}
+
+/// A document in the `projects` collection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Project {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub name: String,
+    pub template_link: String,
+    pub owner: ObjectId,
+    #[serde(default)]
+    pub contributors: Vec<ObjectId>,
+    /// Roles keyed by the contributor's hex id.
+    #[serde(default)]
+    pub member_roles: HashMap<String, ProjectRole>,
+    pub tier_container_html: String,
+    pub image_carousel_html: String,
+    /// Missing on projects created before the structured model until the migration parses their HTML.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier_list: Option<TierList>,
+    /// Sequence number of the latest revision; projects from before revisions existed start at 0.
+    #[serde(default)]
+    pub revision: i64,
+    /// Bumped by every save, including edits coalesced into the latest revision, so clients can order updates.
+    #[serde(default)]
+    pub version: i64,
+    /// Set while the project sits in the trash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime>,
+}
+
+impl Project {
+    pub fn is_trashed(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    pub fn contents(&self) -> ProjectContentsResponse {
+        contents(&self.tier_container_html, &self.image_carousel_html, &self.tier_list)
+    }
+}
+
+/// Falls back to parsing the HTML for documents written before tier lists were stored.
+fn contents(
+    tier_container_html: &str,
+    image_carousel_html: &str,
+    tier_list: &Option<TierList>,
+) -> ProjectContentsResponse {
+    ProjectContentsResponse {
+        tier_container_html: tier_container_html.to_string(),
+        image_carousel_html: image_carousel_html.to_string(),
+        tier_list: tier_list.clone().unwrap_or_else(|| parse_tier_list(tier_container_html, image_carousel_html)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvitationStatus {
+    Pending,
+    Accepted,
+    Declined,
+    Expired,
+}
+
+impl InvitationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvitationStatus::Pending => "pending",
+            InvitationStatus::Accepted => "accepted",
+            InvitationStatus::Declined => "declined",
+            InvitationStatus::Expired => "expired",
+        }
+    }
+}
+
+/// A document in the `revisions` collection: the project's contents after one edit or a burst of them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Revision {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub project_id: ObjectId,
+    /// Counts up from 1 within a project.
+    pub sequence: i64,
+    pub author: ObjectId,
+    pub created_at: DateTime,
+    pub updated_at: DateTime,
+    /// How many edits were coalesced into this revision.
+    pub edits: i64,
+    pub tier_container_html: String,
+    pub image_carousel_html: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier_list: Option<TierList>,
+    /// The revision this one restored, if it came from a restore rather than an edit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restored_from: Option<i64>,
+    /// The project's `version` once these contents were saved.
+    #[serde(default)]
+    pub version: i64,
+}
+
+impl Revision {
+    pub fn contents(&self) -> ProjectContentsResponse {
+        contents(&self.tier_container_html, &self.image_carousel_html, &self.tier_list)
+    }
+}
+
+/// A document in the `invitations` collection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Invitation {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub email: String,
+    pub project_id: ObjectId,
+    pub invited_by: ObjectId,
+    pub status: InvitationStatus,
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub responded_at: Option<DateTime>,
+}
+
+/// A document in the `sessions` collection; one per login.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: ObjectId,
+    pub created_at: DateTime,
+    pub last_refreshed_at: DateTime,
+    pub revoked: bool,
+}
+
+/// A document in the `refresh_tokens` collection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RefreshToken {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: ObjectId,
+    /// The session the token belongs to.
+    pub family_id: ObjectId,
+    pub token_hash: String,
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+    pub used: bool,
+    pub revoked: bool,
+}
+
+/// What a single-use emailed code is for; each purpose has its own collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmailTokenPurpose {
+    PasswordReset,
+    EmailVerification,
+}
+
+/// A document in the `password_resets` or `email_verifications` collection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmailToken {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub user_id: ObjectId,
+    pub token_hash: String,
+    pub created_at: DateTime,
+    pub expires_at: DateTime,
+    pub used: bool,
+}
+
+/// A document in the `invite_links` collection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InviteLink {
+    #[serde(rename = "_id")]
+    pub id: ObjectId,
+    pub project_id: ObjectId,
+    pub created_by: ObjectId,
+    pub token_hash: String,
+    pub role: ProjectRole,
+    pub created_at: DateTime,
+    pub expires_at: Option<DateTime>,
+    pub max_uses: Option<i64>,
+    pub uses: i64,
+    pub revoked: bool,
+}
+
+impl InviteLink {
+    /// Not revoked, not expired and not used up.
+    pub fn is_usable(&self, now: DateTime) -> bool {
+        !self.revoked
+            && self.expires_at.is_none_or(|expires_at| expires_at > now)
+            && self.max_uses.is_none_or(|max_uses| self.uses < max_uses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db_constants::{ProjectFields, UserFields};
+    use mongodb::bson::{doc, from_document, to_document};
+
+    #[test]
+    fn user_fields_match_db_constants() {
+        let user = User {
+            id: ObjectId::new(),
+            email: "user@example.com".to_string(),
+            display_name: "User".to_string(),
+            passwd_hash: "hash".to_string(),
+            verified: false,
+            projects: vec![ObjectId::new()],
+        };
+        let document = to_document(&user).unwrap();
+
+        for field in [
+            UserFields::ID, UserFields::EMAIL, UserFields::PASSWD_HASH,
+            UserFields::VERIFIED, UserFields::PROJECTS,
+        ] {
+            assert!(document.contains_key(field), "missing {field}");
+        }
+        assert_eq!(from_document::<User>(document).unwrap(), user);
+    }
+
+    #[test]
+    fn legacy_user_without_verified_flag_counts_as_verified() {
+        let user: User = from_document(doc! {
+            UserFields::ID: ObjectId::new(),
+            UserFields::EMAIL: "user@example.com",
+            "display_name": "User",
+            UserFields::PASSWD_HASH: "hash",
+        }).unwrap();
+
+        assert!(user.verified);
+        assert!(user.projects.is_empty());
+    }
+
+    #[test]
+    fn project_fields_match_db_constants() {
+        let contributor = ObjectId::new();
+        let project = Project {
+            id: ObjectId::new(),
+            name: "Project".to_string(),
+            template_link: "template".to_string(),
+            owner: ObjectId::new(),
+            contributors: vec![contributor],
+            member_roles: HashMap::from([(contributor.to_hex(), ProjectRole::Viewer)]),
+            tier_container_html: "<div></div>".to_string(),
+            image_carousel_html: "<div></div>".to_string(),
+            tier_list: Some(TierList::default()),
+            revision: 3,
+            version: 5,
+            deleted_at: Some(DateTime::now()),
+        };
+        let document = to_document(&project).unwrap();
+
+        for field in [
+            ProjectFields::ID, ProjectFields::TEMPLATE_LINK, ProjectFields::CONTRIBUTORS,
+            ProjectFields::MEMBER_ROLES, ProjectFields::TIER_CONTAINER_HTML, ProjectFields::IMAGE_CAROUSEL_HTML,
+            ProjectFields::TIER_LIST, ProjectFields::REVISION, ProjectFields::VERSION, ProjectFields::DELETED_AT,
+        ] {
+            assert!(document.contains_key(field), "missing {field}");
+        }
+        assert_eq!(document.get_document(ProjectFields::MEMBER_ROLES).unwrap().get_str(contributor.to_hex()), Ok("viewer"));
+        assert_eq!(from_document::<Project>(document).unwrap(), project);
+    }
+
+    #[test]
+    fn malformed_project_is_rejected() {
+        let project = from_document::<Project>(doc! {
+            ProjectFields::ID: ObjectId::new(),
+            "name": "Project",
+            ProjectFields::TEMPLATE_LINK: "template",
+            "owner": "not-an-object-id",
+            ProjectFields::TIER_CONTAINER_HTML: "",
+            ProjectFields::IMAGE_CAROUSEL_HTML: "",
+        });
+
+        assert!(project.is_err());
+    }
+}
@@ -0,0 +1,103 @@
+use crate::authentication::hash_password;
+use crate::email::normalize_email;
+use crate::mailer::Mail;
+use crate::models::{EmailToken, EmailTokenPurpose};
+use crate::session::revoke_user_sessions;
+use crate::token::{expires_after, generate_token, hash_token};
+use crate::error::SharedTierListError::Validation;
+use crate::{error, AppState};
+use axum::extract::State;
+use crate::extract::Json;
+use chrono::Duration;
+use http::StatusCode;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::Deserialize;
+use std::sync::Arc;
+
+const PASSWORD_RESET_LIFETIME_MINUTES: i64 = 60;
+const MAX_PASSWORD_RESETS_PER_HOUR: u64 = 5;
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetRequest {
+    email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetConfirmRequest {
+    token: String,
+    new_password: String,
+}
+
+pub async fn request_password_reset(
+    State(app_state): State<Arc<AppState>>,
+    Json(payload): Json<PasswordResetRequest>,
+) -> error::Result<StatusCode> {
+    let user_opt = app_state.store.find_user_by_email(&normalize_email(&payload.email)).await?;
+
+    // Answer the same way whether or not the account exists so emails cannot be probed.
+    let Some(user) = user_opt else {
+        return Ok(StatusCode::ACCEPTED);
+    };
+
+    let user_id = user.id;
+
+    // Past the limit the request is dropped quietly, as an error would tell whether the account exists.
+    let recent = app_state.store
+        .count_email_tokens_since(EmailTokenPurpose::PasswordReset, user_id, expires_after(-Duration::hours(1)))
+        .await?;
+
+    if recent >= MAX_PASSWORD_RESETS_PER_HOUR {
+        tracing::debug!("Not sending another password reset for {user_id} this hour");
+        return Ok(StatusCode::ACCEPTED);
+    }
+
+    let token = generate_token();
+
+    app_state.store.insert_email_token(EmailTokenPurpose::PasswordReset, &EmailToken {
+        id: ObjectId::new(),
+        user_id,
+        token_hash: hash_token(&token),
+        created_at: DateTime::now(),
+        expires_at: expires_after(Duration::minutes(PASSWORD_RESET_LIFETIME_MINUTES)),
+        used: false,
+    }).await?;
+
+    app_state.mailer.send(Mail {
+        to: user.email,
+        subject: "Reset your Shared Tier Lists password".to_string(),
+        body: format!(
+            "Use this code to reset your password within {PASSWORD_RESET_LIFETIME_MINUTES} minutes:\n\n{token}\n\n\
+            If you did not ask for a reset you can ignore this email."
+        ),
+    }).await?;
+
+    tracing::debug!("Sent password reset for {user_id}");
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub async fn confirm_password_reset(
+    State(app_state): State<Arc<AppState>>,
+    Json(payload): Json<PasswordResetConfirmRequest>,
+) -> error::Result<StatusCode> {
+    let reset = app_state.store
+        .consume_email_token(EmailTokenPurpose::PasswordReset, &hash_token(&payload.token))
+        .await?
+        .ok_or_else(|| Validation("Reset code is invalid or has expired".to_string()))?;
+
+    let user_id = reset.user_id;
+
+    // Any other code mailed before this one would otherwise still change the new password.
+    app_state.store.use_up_email_tokens(EmailTokenPurpose::PasswordReset, user_id).await?;
+
+    let password_hash = hash_password(&payload.new_password)?;
+
+    app_state.store.set_password_hash(user_id, &password_hash).await?;
+
+    revoke_user_sessions(&app_state, user_id).await?;
+
+    tracing::debug!("Reset password for {user_id}");
+
+    Ok(StatusCode::OK)
+}
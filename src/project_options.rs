@@ -1,180 +1,130 @@
-use crate::db_constants::{Collections, ProjectFields, UserFields};
-use crate::{error, AppState, ProjectContents};
+use crate::error::SharedTierListError::{Forbidden, NotFound};
+use crate::{error, AppState};
 use axum::extract::State;
-use axum::Json;
+use crate::extract::Json;
 use http::StatusCode;
-use mongodb::bson::{doc, oid::ObjectId, Array, Document};
-use serde::Deserialize;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
-use axum_extra::{
-    TypedHeader,
-    headers::{Authorization, authorization::Bearer},
-};
-use mongodb::Database;
-use crate::authentication::authenticate_user;
-use crate::invite::invite_users;
+use crate::models::Project;
+use crate::authentication::AuthenticatedUser;
+use crate::authorization::authorize_project;
+use crate::invite::{invite_users, InviteResponse};
+use crate::store::RevisionSource;
+use crate::tier_list::TierList;
+use crate::ws::notify_project_deleted;
+use crate::ws_types::ProjectContentsResponse;
 
 #[derive(Deserialize)]
 pub struct CreateProjectRequest {
-    user_id: ObjectId,
     project_name: String,
     template_link: String,
-    tier_container_html: String,
-    image_carousel_html: String,
+    tier_container_html: Option<String>,
+    image_carousel_html: Option<String>,
+    tier_list: Option<TierList>,
     initial_invitations: Vec<String>,
 }
 
+#[derive(Serialize)]
+pub struct CreateProjectResponse {
+    project_id: ObjectId,
+    invitations: InviteResponse,
+}
+
 #[derive(Deserialize)]
 pub struct OpenProjectRequest {
-    user_id: ObjectId,
     project_id: ObjectId,
 }
 
 #[derive(Deserialize)]
 pub struct DeleteProjectRequest {
-    user_id: ObjectId,
     project_id: ObjectId,
 }
 
 pub async fn create_project(
     State(app_state): State<Arc<AppState>>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    auth_user: AuthenticatedUser,
     Json(payload): Json<CreateProjectRequest>,
-) -> Result<StatusCode, StatusCode> {
-    authenticate_user(app_state.clone(), auth).await
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
+) -> error::Result<(StatusCode, Json<CreateProjectResponse>)> {
+    if app_state.verification_policy.require_for_invitations && !auth_user.verified && !payload.initial_invitations.is_empty() {
+        return Err(Forbidden("Verify your email address before inviting people".to_string()));
+    }
 
-    let projects = app_state.db.collection::<Document>(Collections::PROJECTS);
-    
-    let project = doc! {
-        ProjectFields::NAME: payload.project_name,
-        ProjectFields::TEMPLATE_LINK: payload.template_link,
-        ProjectFields::OWNER: payload.user_id,
-        ProjectFields::CONTRIBUTORS: [],
-        ProjectFields::TIER_CONTAINER_HTML: payload.tier_container_html.clone(),
-        ProjectFields::IMAGE_CAROUSEL_HTML: payload.image_carousel_html.clone()
+    let contents = ProjectContentsResponse::from_request(
+        payload.tier_container_html,
+        payload.image_carousel_html,
+        payload.tier_list,
+    )?;
+
+    let project = Project {
+        id: ObjectId::new(),
+        name: payload.project_name,
+        template_link: payload.template_link,
+        owner: auth_user.user_id,
+        contributors: vec![],
+        member_roles: HashMap::new(),
+        tier_container_html: contents.tier_container_html.clone(),
+        image_carousel_html: contents.image_carousel_html.clone(),
+        tier_list: Some(contents.tier_list.clone()),
+        revision: 0,
+        version: 0,
+        deleted_at: None,
     };
 
-    let project = projects.insert_one(project.clone()).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    app_state.store.insert_project(&project).await?;
+    app_state.store.save_revision(project.id, auth_user.user_id, &contents, RevisionSource::Created).await?;
 
     tracing::debug!("Created Project");
 
-    let project_id = match project.inserted_id.as_object_id() {
-        Some(id) => id,
-        None => return Err(StatusCode::INTERNAL_SERVER_ERROR)
-    };
+    let project_id = project.id;
 
-    let users = app_state.db.collection::<Document>(Collections::USERS);
-    users.update_one(
-        doc! { UserFields::ID: payload.user_id },
-        doc! { "$addToSet": { UserFields::PROJECTS: project_id } })
-        .await.map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    app_state.store.add_project_to_users(&[auth_user.user_id], project_id).await?;
 
-    invite_users(app_state.db.clone(), project_id, payload.initial_invitations).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let results = invite_users(&app_state, project_id, auth_user.user_id, payload.initial_invitations).await?;
 
     tracing::debug!("Invited Users");
     
-    Ok(StatusCode::CREATED)
+    Ok((StatusCode::CREATED, Json(CreateProjectResponse {
+        project_id,
+        invitations: InviteResponse { results },
+    })))
 }
 
 pub async fn open_project(
     State(app_state): State<Arc<AppState>>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    auth_user: AuthenticatedUser,
     Json(payload): Json<OpenProjectRequest>,
-) -> Result<Json<ProjectContents>, StatusCode> {
-    authenticate_user(app_state.clone(), auth).await
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
-
-    let projects = app_state.db.collection::<Document>(Collections::PROJECTS);
-
-    let project_opt = projects
-        .find_one(doc! { ProjectFields::ID: payload.project_id })
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    if let Some(project) = project_opt {
-        let res = ProjectContents {
-            tier_container_html: project.get_str(ProjectFields::TIER_CONTAINER_HTML)
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.to_string(),
-            image_carousel_html: project.get_str(ProjectFields::IMAGE_CAROUSEL_HTML)
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?.to_string(),
-        };
-        Ok(Json(res))
-    } else {
-        Err(StatusCode::NOT_FOUND)
-    }
-}
-
-pub async fn remove_project_from_user_list(
-    db: Database,
-    user_id: ObjectId,
-    project_id: ObjectId
-) -> error::Result<()> {
-    let users = db.collection::<Document>(Collections::USERS);
-
-    users.find_one_and_update(
-        doc! { UserFields::ID: user_id },
-        doc! { "$pull": { UserFields::PROJECTS: project_id } }
-    ).await?;
-
-    Ok(())
-}
-
-pub async fn remove_project_from_contributors_lists(
-    db: Database,
-    project_id: ObjectId,
-    contributors: &Array
-) -> error::Result<()> {
-    for user in contributors {
-        if let Some(user_id) = user.as_object_id() {
-            remove_project_from_user_list(db.clone(), user_id, project_id).await?;
-        }
-    }
+) -> error::Result<Json<ProjectContentsResponse>> {
+    let (project, _) = authorize_project(app_state.store.as_ref(), auth_user.user_id, payload.project_id).await?;
 
-    Ok(())
+    Ok(Json(project.contents()))
 }
 
+/// Moves the project into the trash; the purge job removes it for good once the retention period ends.
 pub async fn delete_project(
     State(app_state): State<Arc<AppState>>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>,
+    auth_user: AuthenticatedUser,
     Json(payload): Json<DeleteProjectRequest>,
-) -> Result<StatusCode, StatusCode> {
-    authenticate_user(app_state.clone(), auth).await
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
-
-    let projects = app_state.db.collection::<Document>(Collections::PROJECTS);
-
-    let project_opt = projects.find_one(
-        doc! { ProjectFields::ID: payload.project_id }).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+) -> error::Result<StatusCode> {
+    let project_opt = app_state.store.find_project(payload.project_id).await?;
 
-    match project_opt {
-        None => {
-            Err(StatusCode::NOT_FOUND)
-        }
-        Some(project) => {
-            let owner = project.get_object_id(ProjectFields::OWNER)
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-            if owner != payload.user_id {
-                return Err(StatusCode::UNAUTHORIZED);
-            }
+    let Some(project) = project_opt.filter(|project| !project.is_trashed()) else {
+        return Err(NotFound("Project not found".to_string()));
+    };
 
-            let project_id = project.get_object_id(ProjectFields::ID)
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if project.owner != auth_user.user_id {
+        return Err(Forbidden("Only the project owner can delete it".to_string()));
+    }
 
-            remove_project_from_user_list(app_state.db.clone(), owner, project_id).await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !app_state.store.trash_project(project.id, DateTime::now()).await? {
+        return Err(NotFound("Project not found".to_string()));
+    }
 
-            let contributors = project.get_array(ProjectFields::CONTRIBUTORS)
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    notify_project_deleted(&app_state, project.id).await;
 
-            remove_project_from_contributors_lists(app_state.db.clone(), project_id, contributors).await
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    tracing::debug!("Moved project {} to the trash", project.id);
 
-            Ok(StatusCode::OK)
-        }
-    }
+    Ok(StatusCode::OK)
 }
@@ -0,0 +1,99 @@
+use crate::authentication::{issue_access_token, LoginResponse};
+use crate::session::{revoke_session, touch_session};
+use crate::token::{expires_after, generate_token, hash_token};
+use crate::error::SharedTierListError::StatusCodeError;
+use crate::models::RefreshToken;
+use crate::{error, AppState};
+use axum::extract::State;
+use crate::extract::Json;
+use http::StatusCode;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct RefreshRequest {
+    refresh_token: String,
+}
+
+/// Stores a new refresh token in `family_id` and returns the raw token for the client.
+pub async fn issue_refresh_token(
+    app_state: &AppState,
+    user_id: ObjectId,
+    family_id: ObjectId,
+) -> error::Result<String> {
+    let token = generate_token();
+
+    app_state.store.insert_refresh_token(&RefreshToken {
+        id: ObjectId::new(),
+        user_id,
+        family_id,
+        token_hash: hash_token(&token),
+        created_at: DateTime::now(),
+        expires_at: expires_after(app_state.refresh_token_lifetime),
+        used: false,
+        revoked: false,
+    }).await?;
+
+    Ok(token)
+}
+
+pub async fn refresh(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RefreshRequest>,
+) -> error::Result<Json<LoginResponse>> {
+    let token_hash = hash_token(&payload.refresh_token);
+
+    // Claiming the token atomically means two concurrent refreshes cannot both rotate it.
+    let claimed_opt = state.store.claim_refresh_token(&token_hash).await?;
+
+    let claimed = match claimed_opt {
+        Some(claimed) => claimed,
+        None => {
+            let reused_opt = state.store.find_refresh_token(&token_hash).await?;
+
+            if let Some(reused) = reused_opt {
+                // A rotated token came back, so assume it leaked and end the whole session.
+                let family_id = reused.family_id;
+
+                tracing::debug!("Refresh token reuse detected, revoking session {family_id}");
+
+                revoke_session(&state, family_id).await?;
+            }
+
+            return Err(StatusCodeError(StatusCode::UNAUTHORIZED));
+        }
+    };
+
+    if claimed.expires_at < DateTime::now() {
+        return Err(StatusCodeError(StatusCode::UNAUTHORIZED));
+    }
+
+    let user_id = claimed.user_id;
+    let session_id = claimed.family_id;
+
+    touch_session(&state, session_id).await?;
+
+    let refresh_token = issue_refresh_token(&state, user_id, session_id).await?;
+    let token = issue_access_token(&state, user_id, session_id)?;
+
+    Ok(Json(LoginResponse {
+        token,
+        refresh_token,
+        user_id: user_id.to_string(),
+    }))
+}
+
+pub async fn logout(
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<RefreshRequest>,
+) -> error::Result<StatusCode> {
+    let token_opt = state.store.find_refresh_token(&hash_token(&payload.refresh_token)).await?;
+
+    if let Some(token) = token_opt {
+        revoke_session(&state, token.family_id).await?;
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
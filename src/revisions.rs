@@ -0,0 +1,146 @@
+use crate::authentication::AuthenticatedUser;
+use crate::authorization::authorize_project;
+use crate::error::SharedTierListError::{Forbidden, NotFound};
+use crate::models::Revision;
+use crate::store::RevisionSource;
+use crate::ws::{broadcast_project_update, project_edit_lock};
+use crate::ws_types::ProjectContentsResponse;
+use crate::{error, AppState};
+use axum::extract::State;
+use crate::extract::Json;
+use chrono::Duration;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Edits by one author within this long of their revision starting are folded into it, so a drag
+/// session becomes one revision rather than dozens.
+pub const REVISION_COALESCE_WINDOW: Duration = Duration::seconds(60);
+
+#[derive(Deserialize)]
+pub struct RevisionsRequest {
+    project_id: ObjectId,
+}
+
+#[derive(Serialize)]
+pub struct RevisionsResponse {
+    revisions: Vec<RevisionSummary>,
+}
+
+#[derive(Serialize)]
+pub struct RevisionSummary {
+    sequence: i64,
+    author: ObjectId,
+    created_at: String,
+    updated_at: String,
+    edits: i64,
+    restored_from: Option<i64>,
+}
+
+impl From<&Revision> for RevisionSummary {
+    fn from(revision: &Revision) -> RevisionSummary {
+        RevisionSummary {
+            sequence: revision.sequence,
+            author: revision.author,
+            created_at: revision.created_at.to_string(),
+            updated_at: revision.updated_at.to_string(),
+            edits: revision.edits,
+            restored_from: revision.restored_from,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RevisionRequest {
+    project_id: ObjectId,
+    sequence: i64,
+}
+
+#[derive(Serialize)]
+pub struct RevisionResponse {
+    #[serde(flatten)]
+    summary: RevisionSummary,
+    contents: ProjectContentsResponse,
+}
+
+#[derive(Serialize)]
+pub struct RestoreRevisionResponse {
+    sequence: i64,
+}
+
+/// Every revision of the project, newest first, without their contents.
+pub async fn list_revisions(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Json(payload): Json<RevisionsRequest>,
+) -> error::Result<Json<RevisionsResponse>> {
+    authorize_project(app_state.store.as_ref(), user_id, payload.project_id).await?;
+
+    let revisions = app_state.store.project_revisions(payload.project_id)
+        .await?
+        .iter()
+        .map(RevisionSummary::from)
+        .collect();
+
+    Ok(Json(RevisionsResponse {
+        revisions
+    }))
+}
+
+pub async fn get_revision(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Json(payload): Json<RevisionRequest>,
+) -> error::Result<Json<RevisionResponse>> {
+    authorize_project(app_state.store.as_ref(), user_id, payload.project_id).await?;
+
+    let revision = app_state.store.find_revision(payload.project_id, payload.sequence)
+        .await?
+        .ok_or_else(|| NotFound("Revision not found".to_string()))?;
+
+    Ok(Json(RevisionResponse {
+        summary: RevisionSummary::from(&revision),
+        contents: revision.contents(),
+    }))
+}
+
+/// Puts the project back to an earlier revision by recording its contents as a new one.
+pub async fn restore_revision(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Json(payload): Json<RevisionRequest>,
+) -> error::Result<Json<RestoreRevisionResponse>> {
+    let (_, role) = authorize_project(app_state.store.as_ref(), user_id, payload.project_id).await?;
+
+    if !role.can_edit() {
+        return Err(Forbidden("Your role cannot edit this project".to_string()));
+    }
+
+    let revision = app_state.store.find_revision(payload.project_id, payload.sequence)
+        .await?
+        .ok_or_else(|| NotFound("Revision not found".to_string()))?;
+
+    // Taken before saving so an operation on an open socket cannot slip in between and be overwritten.
+    let edit_lock = project_edit_lock(&app_state, payload.project_id).await;
+    let _edit_guard = match &edit_lock {
+        Some(edit_lock) => Some(edit_lock.lock().await),
+        None => None,
+    };
+
+    let contents = revision.contents();
+    let source = RevisionSource::Restore {
+        sequence: revision.sequence,
+    };
+
+    let restored = app_state.store.save_revision(payload.project_id, user_id, &contents, source)
+        .await?
+        .ok_or_else(|| NotFound("Project not found".to_string()))?;
+
+    broadcast_project_update(&app_state, payload.project_id, restored.version, contents).await;
+
+    tracing::debug!("Restored project {} to revision {}", payload.project_id, revision.sequence);
+
+    Ok(Json(RestoreRevisionResponse {
+        sequence: restored.sequence,
+    }))
+}
@@ -0,0 +1,123 @@
+use crate::authentication::AuthSession;
+use crate::error::SharedTierListError::NotFound;
+use crate::models::Session;
+use crate::{error, AppState};
+use axum::extract::State;
+use crate::extract::Json;
+use http::StatusCode;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct RevokeSessionRequest {
+    session_id: ObjectId,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionsResponse {
+    sessions: Vec<SessionInfo>,
+}
+
+#[derive(Debug, Serialize)]
+struct SessionInfo {
+    session_id: ObjectId,
+    created_at: String,
+    last_refreshed_at: String,
+    current: bool,
+}
+
+pub async fn create_session(app_state: &AppState, user_id: ObjectId) -> error::Result<ObjectId> {
+    let now = DateTime::now();
+    let session = Session {
+        id: ObjectId::new(),
+        user_id,
+        created_at: now,
+        last_refreshed_at: now,
+        revoked: false,
+    };
+
+    app_state.store.insert_session(&session).await?;
+
+    Ok(session.id)
+}
+
+pub async fn session_is_active(
+    app_state: &AppState,
+    user_id: ObjectId,
+    session_id: ObjectId,
+) -> error::Result<bool> {
+    let session_opt = app_state.store.find_active_session(user_id, session_id).await?;
+
+    Ok(session_opt.is_some())
+}
+
+pub async fn touch_session(app_state: &AppState, session_id: ObjectId) -> error::Result<()> {
+    app_state.store.touch_session(session_id).await
+}
+
+/// Revokes a session, its refresh tokens, and disconnects any WebSocket it opened.
+pub async fn revoke_session(app_state: &AppState, session_id: ObjectId) -> error::Result<()> {
+    app_state.store.revoke_session(session_id).await?;
+    app_state.store.revoke_refresh_token_family(session_id).await?;
+
+    // Nobody listening just means no sockets are open.
+    let _ = app_state.session_revocations.send(session_id);
+
+    Ok(())
+}
+
+pub async fn list_sessions(
+    State(app_state): State<Arc<AppState>>,
+    AuthSession { user_id, session_id: current_session_id }: AuthSession,
+) -> error::Result<Json<SessionsResponse>> {
+    let session_infos = app_state.store.active_sessions(user_id)
+        .await?
+        .into_iter()
+        .map(|session| SessionInfo {
+            session_id: session.id,
+            created_at: session.created_at.to_string(),
+            last_refreshed_at: session.last_refreshed_at.to_string(),
+            current: session.id == current_session_id,
+        })
+        .collect();
+
+    Ok(Json(SessionsResponse {
+        sessions: session_infos
+    }))
+}
+
+pub async fn revoke_session_handler(
+    State(app_state): State<Arc<AppState>>,
+    AuthSession { user_id, .. }: AuthSession,
+    Json(payload): Json<RevokeSessionRequest>,
+) -> error::Result<StatusCode> {
+    let is_own_session = session_is_active(&app_state, user_id, payload.session_id).await?;
+
+    if !is_own_session {
+        return Err(NotFound("Session not found".to_string()));
+    }
+
+    revoke_session(&app_state, payload.session_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn revoke_all_sessions(
+    State(app_state): State<Arc<AppState>>,
+    AuthSession { user_id, .. }: AuthSession,
+) -> error::Result<StatusCode> {
+    revoke_user_sessions(&app_state, user_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Logs a user out everywhere, including the session making the request.
+pub async fn revoke_user_sessions(app_state: &AppState, user_id: ObjectId) -> error::Result<()> {
+    for session in app_state.store.active_sessions(user_id).await? {
+        revoke_session(app_state, session.id).await?;
+    }
+
+    Ok(())
+}
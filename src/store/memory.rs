@@ -0,0 +1,746 @@
+use super::{
+    absorb_edit, coalesces_into, new_revision, EmailTokenStore, InvitationStore, InviteLinkStore, ProjectStore,
+    RevisionSource, RevisionStore, SessionStore, UserStore,
+};
+use crate::authorization::ProjectRole;
+use crate::error;
+use crate::error::SharedTierListError::Conflict;
+use crate::models::{
+    EmailToken, EmailTokenPurpose, Invitation, InvitationStatus, InviteLink, Project, RefreshToken, Revision, Session,
+    User,
+};
+use crate::tier_list::TierList;
+use crate::ws_types::ProjectContentsResponse;
+use async_trait::async_trait;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+/// A store that keeps everything in process memory, for tests and local development.
+#[derive(Default)]
+pub struct MemoryStore {
+    data: Mutex<Data>,
+}
+
+#[derive(Default)]
+struct Data {
+    users: Vec<User>,
+    projects: Vec<Project>,
+    revisions: Vec<Revision>,
+    invitations: Vec<Invitation>,
+    sessions: Vec<Session>,
+    refresh_tokens: Vec<RefreshToken>,
+    email_tokens: HashMap<EmailTokenPurpose, Vec<EmailToken>>,
+    invite_links: Vec<InviteLink>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+
+    fn data(&self) -> MutexGuard<'_, Data> {
+        // Every write leaves the data consistent, so a panic elsewhere does not poison it.
+        self.data.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn is_open(invitation: &Invitation, now: DateTime) -> bool {
+    invitation.status == InvitationStatus::Pending && invitation.expires_at > now
+}
+
+#[async_trait]
+impl UserStore for MemoryStore {
+    async fn find_user(&self, user_id: ObjectId) -> error::Result<Option<User>> {
+        Ok(self.data().users.iter().find(|user| user.id == user_id).cloned())
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> error::Result<Option<User>> {
+        Ok(self.data().users.iter().find(|user| user.email == email).cloned())
+    }
+
+    async fn insert_user(&self, user: &User) -> error::Result<()> {
+        let mut data = self.data();
+
+        if data.users.iter().any(|existing| existing.email == user.email) {
+            return Err(Conflict("An account with this email already exists".to_string()));
+        }
+
+        data.users.push(user.clone());
+        Ok(())
+    }
+
+    async fn set_user_verified(&self, user_id: ObjectId) -> error::Result<()> {
+        if let Some(user) = self.data().users.iter_mut().find(|user| user.id == user_id) {
+            user.verified = true;
+        }
+        Ok(())
+    }
+
+    async fn set_password_hash(&self, user_id: ObjectId, passwd_hash: &str) -> error::Result<()> {
+        if let Some(user) = self.data().users.iter_mut().find(|user| user.id == user_id) {
+            user.passwd_hash = passwd_hash.to_string();
+        }
+        Ok(())
+    }
+
+    async fn add_project_to_users(&self, user_ids: &[ObjectId], project_id: ObjectId) -> error::Result<()> {
+        for user in self.data().users.iter_mut().filter(|user| user_ids.contains(&user.id)) {
+            if !user.projects.contains(&project_id) {
+                user.projects.push(project_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProjectStore for MemoryStore {
+    async fn find_project(&self, project_id: ObjectId) -> error::Result<Option<Project>> {
+        Ok(self.data().projects.iter().find(|project| project.id == project_id).cloned())
+    }
+
+    async fn find_projects_by_template(
+        &self,
+        project_ids: &[ObjectId],
+        template_link: &str,
+    ) -> error::Result<Vec<Project>> {
+        Ok(self.data().projects.iter()
+            .filter(|project| {
+                project_ids.contains(&project.id) && project.template_link == template_link && !project.is_trashed()
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn insert_project(&self, project: &Project) -> error::Result<()> {
+        self.data().projects.push(project.clone());
+        Ok(())
+    }
+
+    async fn add_contributors(&self, project_id: ObjectId, user_ids: &[ObjectId]) -> error::Result<()> {
+        if let Some(project) = self.data().projects.iter_mut().find(|project| project.id == project_id) {
+            for user_id in user_ids {
+                if !project.contributors.contains(user_id) {
+                    project.contributors.push(*user_id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn set_member_role(&self, project_id: ObjectId, member_id: ObjectId, role: ProjectRole) -> error::Result<()> {
+        if let Some(project) = self.data().projects.iter_mut().find(|project| project.id == project_id) {
+            project.member_roles.insert(member_id.to_hex(), role);
+        }
+        Ok(())
+    }
+
+    async fn add_member_with_role(&self, project_id: ObjectId, member_id: ObjectId, role: ProjectRole) -> error::Result<()> {
+        if let Some(project) = self.data().projects.iter_mut().find(|project| project.id == project_id) {
+            if !project.contributors.contains(&member_id) {
+                project.contributors.push(member_id);
+            }
+            project.member_roles.insert(member_id.to_hex(), role);
+        }
+        Ok(())
+    }
+
+    async fn projects_without_tier_list(&self) -> error::Result<Vec<Project>> {
+        Ok(self.data().projects.iter()
+            .filter(|project| project.tier_list.is_none())
+            .cloned()
+            .collect())
+    }
+
+    async fn set_tier_list(&self, project_id: ObjectId, tier_list: &TierList) -> error::Result<bool> {
+        let mut data = self.data();
+        let project_opt = data.projects.iter_mut()
+            .find(|project| project.id == project_id && project.tier_list.is_none());
+
+        Ok(project_opt.map(|project| project.tier_list = Some(tier_list.clone())).is_some())
+    }
+
+    async fn trash_project(&self, project_id: ObjectId, deleted_at: DateTime) -> error::Result<bool> {
+        let mut data = self.data();
+        let project_opt = data.projects.iter_mut()
+            .find(|project| project.id == project_id && !project.is_trashed());
+
+        Ok(project_opt.map(|project| project.deleted_at = Some(deleted_at)).is_some())
+    }
+
+    async fn restore_project(&self, project_id: ObjectId) -> error::Result<bool> {
+        let mut data = self.data();
+        let project_opt = data.projects.iter_mut()
+            .find(|project| project.id == project_id && project.is_trashed());
+
+        Ok(project_opt.map(|project| project.deleted_at = None).is_some())
+    }
+
+    async fn trashed_projects(&self, owner_id: ObjectId) -> error::Result<Vec<Project>> {
+        Ok(self.data().projects.iter()
+            .filter(|project| project.owner == owner_id && project.is_trashed())
+            .cloned()
+            .collect())
+    }
+
+    async fn projects_trashed_before(&self, cutoff: DateTime) -> error::Result<Vec<ObjectId>> {
+        Ok(self.data().projects.iter()
+            .filter(|project| project.deleted_at.is_some_and(|deleted_at| deleted_at < cutoff))
+            .map(|project| project.id)
+            .collect())
+    }
+
+    async fn purge_project(&self, project_id: ObjectId, cutoff: DateTime) -> error::Result<bool> {
+        let mut data = self.data();
+        let before = data.projects.len();
+
+        data.projects.retain(|project| {
+            project.id != project_id || project.deleted_at.is_none_or(|deleted_at| deleted_at >= cutoff)
+        });
+
+        if data.projects.len() == before {
+            return Ok(false);
+        }
+
+        data.invitations.retain(|invitation| invitation.project_id != project_id);
+        data.invite_links.retain(|link| link.project_id != project_id);
+        data.revisions.retain(|revision| revision.project_id != project_id);
+
+        for user in data.users.iter_mut() {
+            user.projects.retain(|id| *id != project_id);
+        }
+
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl RevisionStore for MemoryStore {
+    async fn save_revision(
+        &self,
+        project_id: ObjectId,
+        author: ObjectId,
+        contents: &ProjectContentsResponse,
+        source: RevisionSource,
+    ) -> error::Result<Option<Revision>> {
+        let now = DateTime::now();
+        let mut data = self.data();
+        let data = &mut *data;
+
+        let Some(project) = data.projects.iter_mut().find(|project| project.id == project_id) else {
+            return Ok(None);
+        };
+
+        project.tier_container_html = contents.tier_container_html.clone();
+        project.image_carousel_html = contents.image_carousel_html.clone();
+        project.tier_list = Some(contents.tier_list.clone());
+        project.version += 1;
+
+        let latest_opt = data.revisions.iter_mut()
+            .filter(|revision| revision.project_id == project_id)
+            .max_by_key(|revision| revision.sequence);
+
+        if let Some(latest) = latest_opt.filter(|latest| coalesces_into(latest, author, source)) {
+            absorb_edit(latest, contents, project.version, now);
+            return Ok(Some(latest.clone()));
+        }
+
+        project.revision += 1;
+
+        let revision = new_revision(project, author, contents, source, now);
+        data.revisions.push(revision.clone());
+
+        Ok(Some(revision))
+    }
+
+    async fn project_revisions(&self, project_id: ObjectId) -> error::Result<Vec<Revision>> {
+        let mut revisions: Vec<Revision> = self.data().revisions.iter()
+            .filter(|revision| revision.project_id == project_id)
+            .cloned()
+            .collect();
+
+        revisions.sort_by_key(|revision| std::cmp::Reverse(revision.sequence));
+        Ok(revisions)
+    }
+
+    async fn find_revision(&self, project_id: ObjectId, sequence: i64) -> error::Result<Option<Revision>> {
+        Ok(self.data().revisions.iter()
+            .find(|revision| revision.project_id == project_id && revision.sequence == sequence)
+            .cloned())
+    }
+}
+
+#[async_trait]
+impl InvitationStore for MemoryStore {
+    async fn count_invitations_since(&self, inviter_id: ObjectId, since: DateTime) -> error::Result<u64> {
+        Ok(self.data().invitations.iter()
+            .filter(|invitation| invitation.invited_by == inviter_id && invitation.created_at > since)
+            .count() as u64)
+    }
+
+    async fn create_invitation(&self, invitation: &Invitation) -> error::Result<bool> {
+        let now = DateTime::now();
+        let mut data = self.data();
+
+        let already_open = data.invitations.iter().any(|existing| {
+            existing.email == invitation.email
+                && existing.project_id == invitation.project_id
+                && is_open(existing, now)
+        });
+
+        if already_open {
+            return Ok(false);
+        }
+
+        data.invitations.push(invitation.clone());
+        Ok(true)
+    }
+
+    async fn expire_invitations(&self) -> error::Result<()> {
+        let now = DateTime::now();
+
+        for invitation in self.data().invitations.iter_mut() {
+            if invitation.status == InvitationStatus::Pending && invitation.expires_at <= now {
+                invitation.status = InvitationStatus::Expired;
+            }
+        }
+        Ok(())
+    }
+
+    async fn pending_invitations_for_project(&self, project_id: ObjectId) -> error::Result<Vec<Invitation>> {
+        Ok(self.data().invitations.iter()
+            .filter(|invitation| invitation.project_id == project_id && invitation.status == InvitationStatus::Pending)
+            .cloned()
+            .collect())
+    }
+
+    async fn pending_invitations_for_email(&self, email: &str) -> error::Result<Vec<Invitation>> {
+        Ok(self.data().invitations.iter()
+            .filter(|invitation| invitation.email == email && invitation.status == InvitationStatus::Pending)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_pending_invitations(&self, project_id: ObjectId, email: &str) -> error::Result<u64> {
+        let mut data = self.data();
+        let before = data.invitations.len();
+
+        data.invitations.retain(|invitation| {
+            !(invitation.email == email
+                && invitation.project_id == project_id
+                && invitation.status == InvitationStatus::Pending)
+        });
+
+        Ok((before - data.invitations.len()) as u64)
+    }
+
+    async fn respond_to_invitation(
+        &self,
+        invitation_id: ObjectId,
+        email: &str,
+        status: InvitationStatus,
+    ) -> error::Result<Option<Invitation>> {
+        let now = DateTime::now();
+        let mut data = self.data();
+
+        let invitation_opt = data.invitations.iter_mut().find(|invitation| {
+            invitation.id == invitation_id && invitation.email == email && is_open(invitation, now)
+        });
+
+        Ok(invitation_opt.map(|invitation| {
+            let before = invitation.clone();
+            invitation.status = status;
+            invitation.responded_at = Some(now);
+            before
+        }))
+    }
+
+    async fn find_invitation(&self, invitation_id: ObjectId) -> error::Result<Option<Invitation>> {
+        Ok(self.data().invitations.iter().find(|invitation| invitation.id == invitation_id).cloned())
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn insert_session(&self, session: &Session) -> error::Result<()> {
+        self.data().sessions.push(session.clone());
+        Ok(())
+    }
+
+    async fn find_active_session(&self, user_id: ObjectId, session_id: ObjectId) -> error::Result<Option<Session>> {
+        Ok(self.data().sessions.iter()
+            .find(|session| session.id == session_id && session.user_id == user_id && !session.revoked)
+            .cloned())
+    }
+
+    async fn active_sessions(&self, user_id: ObjectId) -> error::Result<Vec<Session>> {
+        Ok(self.data().sessions.iter()
+            .filter(|session| session.user_id == user_id && !session.revoked)
+            .cloned()
+            .collect())
+    }
+
+    async fn touch_session(&self, session_id: ObjectId) -> error::Result<()> {
+        if let Some(session) = self.data().sessions.iter_mut().find(|session| session.id == session_id) {
+            session.last_refreshed_at = DateTime::now();
+        }
+        Ok(())
+    }
+
+    async fn revoke_session(&self, session_id: ObjectId) -> error::Result<()> {
+        if let Some(session) = self.data().sessions.iter_mut().find(|session| session.id == session_id) {
+            session.revoked = true;
+        }
+        Ok(())
+    }
+
+    async fn insert_refresh_token(&self, token: &RefreshToken) -> error::Result<()> {
+        self.data().refresh_tokens.push(token.clone());
+        Ok(())
+    }
+
+    async fn claim_refresh_token(&self, token_hash: &str) -> error::Result<Option<RefreshToken>> {
+        let mut data = self.data();
+
+        let token_opt = data.refresh_tokens.iter_mut()
+            .find(|token| token.token_hash == token_hash && !token.used && !token.revoked);
+
+        Ok(token_opt.map(|token| {
+            let before = token.clone();
+            token.used = true;
+            before
+        }))
+    }
+
+    async fn find_refresh_token(&self, token_hash: &str) -> error::Result<Option<RefreshToken>> {
+        Ok(self.data().refresh_tokens.iter().find(|token| token.token_hash == token_hash).cloned())
+    }
+
+    async fn revoke_refresh_token_family(&self, family_id: ObjectId) -> error::Result<()> {
+        for token in self.data().refresh_tokens.iter_mut().filter(|token| token.family_id == family_id) {
+            token.revoked = true;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EmailTokenStore for MemoryStore {
+    async fn insert_email_token(&self, purpose: EmailTokenPurpose, token: &EmailToken) -> error::Result<()> {
+        self.data().email_tokens.entry(purpose).or_default().push(token.clone());
+        Ok(())
+    }
+
+    async fn count_email_tokens_since(
+        &self,
+        purpose: EmailTokenPurpose,
+        user_id: ObjectId,
+        since: DateTime,
+    ) -> error::Result<u64> {
+        Ok(self.data().email_tokens.get(&purpose).map_or(0, |tokens| {
+            tokens.iter()
+                .filter(|token| token.user_id == user_id && token.created_at > since)
+                .count() as u64
+        }))
+    }
+
+    async fn use_up_email_tokens(&self, purpose: EmailTokenPurpose, user_id: ObjectId) -> error::Result<()> {
+        if let Some(tokens) = self.data().email_tokens.get_mut(&purpose) {
+            for token in tokens.iter_mut().filter(|token| token.user_id == user_id) {
+                token.used = true;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn consume_email_token(
+        &self,
+        purpose: EmailTokenPurpose,
+        token_hash: &str,
+    ) -> error::Result<Option<EmailToken>> {
+        let now = DateTime::now();
+        let mut data = self.data();
+
+        let token_opt = data.email_tokens.get_mut(&purpose).and_then(|tokens| {
+            tokens.iter_mut().find(|token| token.token_hash == token_hash && !token.used && token.expires_at > now)
+        });
+
+        Ok(token_opt.map(|token| {
+            let before = token.clone();
+            token.used = true;
+            before
+        }))
+    }
+}
+
+#[async_trait]
+impl InviteLinkStore for MemoryStore {
+    async fn insert_invite_link(&self, link: &InviteLink) -> error::Result<()> {
+        self.data().invite_links.push(link.clone());
+        Ok(())
+    }
+
+    async fn usable_invite_links(&self, project_id: ObjectId) -> error::Result<Vec<InviteLink>> {
+        let now = DateTime::now();
+
+        Ok(self.data().invite_links.iter()
+            .filter(|link| link.project_id == project_id && link.is_usable(now))
+            .cloned()
+            .collect())
+    }
+
+    async fn revoke_invite_link(&self, project_id: ObjectId, link_id: ObjectId) -> error::Result<bool> {
+        let mut data = self.data();
+        let link_opt = data.invite_links.iter_mut()
+            .find(|link| link.id == link_id && link.project_id == project_id);
+
+        Ok(link_opt.map(|link| link.revoked = true).is_some())
+    }
+
+    async fn find_usable_invite_link(&self, token_hash: &str) -> error::Result<Option<InviteLink>> {
+        let now = DateTime::now();
+
+        Ok(self.data().invite_links.iter()
+            .find(|link| link.token_hash == token_hash && link.is_usable(now))
+            .cloned())
+    }
+
+    async fn use_invite_link(&self, token_hash: &str) -> error::Result<Option<InviteLink>> {
+        let now = DateTime::now();
+        let mut data = self.data();
+
+        let link_opt = data.invite_links.iter_mut()
+            .find(|link| link.token_hash == token_hash && link.is_usable(now));
+
+        Ok(link_opt.map(|link| {
+            let before = link.clone();
+            link.uses += 1;
+            before
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use crate::test_fixtures::project;
+    use crate::token::expires_after;
+
+    fn invitation(email: &str, project_id: ObjectId, expires_at: DateTime) -> Invitation {
+        Invitation {
+            id: ObjectId::new(),
+            email: email.to_string(),
+            project_id,
+            invited_by: ObjectId::new(),
+            status: InvitationStatus::Pending,
+            created_at: DateTime::now(),
+            expires_at,
+            responded_at: None,
+        }
+    }
+
+    fn refresh_token(family_id: ObjectId, token_hash: &str) -> RefreshToken {
+        RefreshToken {
+            id: ObjectId::new(),
+            user_id: ObjectId::new(),
+            family_id,
+            token_hash: token_hash.to_string(),
+            created_at: DateTime::now(),
+            expires_at: expires_after(Duration::days(1)),
+            used: false,
+            revoked: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn second_account_with_the_same_email_conflicts() {
+        let store = MemoryStore::new();
+        let user = |id| User {
+            id,
+            email: "user@example.com".to_string(),
+            display_name: "User".to_string(),
+            passwd_hash: "hash".to_string(),
+            verified: false,
+            projects: vec![],
+        };
+
+        store.insert_user(&user(ObjectId::new())).await.unwrap();
+        let error = store.insert_user(&user(ObjectId::new())).await.unwrap_err();
+
+        assert!(matches!(error, Conflict(_)));
+    }
+
+    #[tokio::test]
+    async fn open_invitation_is_not_duplicated() {
+        let store = MemoryStore::new();
+        let project_id = ObjectId::new();
+        let expires_at = expires_after(Duration::days(1));
+
+        assert!(store.create_invitation(&invitation("a@example.com", project_id, expires_at)).await.unwrap());
+        assert!(!store.create_invitation(&invitation("a@example.com", project_id, expires_at)).await.unwrap());
+        assert!(store.create_invitation(&invitation("b@example.com", project_id, expires_at)).await.unwrap());
+        assert_eq!(store.pending_invitations_for_project(project_id).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn expired_invitation_cannot_be_accepted() {
+        let store = MemoryStore::new();
+        let expired = invitation("a@example.com", ObjectId::new(), expires_after(Duration::days(-1)));
+        store.create_invitation(&expired).await.unwrap();
+
+        let responded = store.respond_to_invitation(expired.id, "a@example.com", InvitationStatus::Accepted).await;
+        assert_eq!(responded.unwrap(), None);
+
+        store.expire_invitations().await.unwrap();
+        let stored = store.find_invitation(expired.id).await.unwrap().unwrap();
+        assert_eq!(stored.status, InvitationStatus::Expired);
+    }
+
+    #[tokio::test]
+    async fn refresh_token_is_claimed_once() {
+        let store = MemoryStore::new();
+        let family_id = ObjectId::new();
+        store.insert_refresh_token(&refresh_token(family_id, "hash")).await.unwrap();
+
+        assert!(store.claim_refresh_token("hash").await.unwrap().is_some());
+        assert!(store.claim_refresh_token("hash").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn revoked_family_cannot_be_claimed() {
+        let store = MemoryStore::new();
+        let family_id = ObjectId::new();
+        store.insert_refresh_token(&refresh_token(family_id, "hash")).await.unwrap();
+
+        store.revoke_refresh_token_family(family_id).await.unwrap();
+
+        assert!(store.claim_refresh_token("hash").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn invite_link_stops_at_max_uses() {
+        let store = MemoryStore::new();
+        let link = InviteLink {
+            id: ObjectId::new(),
+            project_id: ObjectId::new(),
+            created_by: ObjectId::new(),
+            token_hash: "hash".to_string(),
+            role: ProjectRole::Viewer,
+            created_at: DateTime::now(),
+            expires_at: None,
+            max_uses: Some(1),
+            uses: 0,
+            revoked: false,
+        };
+        store.insert_invite_link(&link).await.unwrap();
+
+        assert!(store.use_invite_link("hash").await.unwrap().is_some());
+        assert!(store.use_invite_link("hash").await.unwrap().is_none());
+        assert!(store.usable_invite_links(link.project_id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn deleting_a_project_cascades() {
+        let store = MemoryStore::new();
+        let owner = ObjectId::new();
+        let project = project(owner);
+        let pending = invitation("a@example.com", project.id, expires_after(Duration::days(1)));
+
+        store.insert_project(&project).await.unwrap();
+        store.insert_user(&User {
+            id: owner,
+            email: "owner@example.com".to_string(),
+            display_name: "Owner".to_string(),
+            passwd_hash: "hash".to_string(),
+            verified: true,
+            projects: vec![project.id],
+        }).await.unwrap();
+        store.create_invitation(&pending).await.unwrap();
+
+        assert!(store.trash_project(project.id, expires_after(-Duration::days(1))).await.unwrap());
+        assert!(store.purge_project(project.id, DateTime::now()).await.unwrap());
+
+        assert_eq!(store.find_project(project.id).await.unwrap(), None);
+        assert_eq!(store.find_invitation(pending.id).await.unwrap(), None);
+        assert!(store.find_user(owner).await.unwrap().unwrap().projects.is_empty());
+        assert!(!store.purge_project(project.id, DateTime::now()).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn trashed_project_is_purged_only_after_the_cutoff() {
+        let store = MemoryStore::new();
+        let project = project(ObjectId::new());
+        store.insert_project(&project).await.unwrap();
+
+        let deleted_at = expires_after(-Duration::days(2));
+        assert!(store.trash_project(project.id, deleted_at).await.unwrap());
+        assert!(!store.trash_project(project.id, DateTime::now()).await.unwrap());
+        assert!(store.find_projects_by_template(&[project.id], "template").await.unwrap().is_empty());
+
+        assert!(store.projects_trashed_before(expires_after(-Duration::days(3))).await.unwrap().is_empty());
+        assert_eq!(store.projects_trashed_before(DateTime::now()).await.unwrap(), vec![project.id]);
+
+        assert!(store.restore_project(project.id).await.unwrap());
+        assert!(!store.restore_project(project.id).await.unwrap());
+        assert!(store.projects_trashed_before(DateTime::now()).await.unwrap().is_empty());
+        assert_eq!(store.find_projects_by_template(&[project.id], "template").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn project_restored_after_listing_is_not_purged() {
+        let store = MemoryStore::new();
+        let project = project(ObjectId::new());
+        store.insert_project(&project).await.unwrap();
+        store.trash_project(project.id, expires_after(-Duration::days(2))).await.unwrap();
+
+        let cutoff = DateTime::now();
+        assert_eq!(store.projects_trashed_before(cutoff).await.unwrap(), vec![project.id]);
+        assert!(store.restore_project(project.id).await.unwrap());
+
+        assert!(!store.purge_project(project.id, cutoff).await.unwrap());
+        assert!(store.find_project(project.id).await.unwrap().is_some());
+
+        // Trashed again after the cutoff, so still not due.
+        store.trash_project(project.id, DateTime::now()).await.unwrap();
+        assert!(!store.purge_project(project.id, cutoff).await.unwrap());
+        assert!(store.find_project(project.id).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn edits_by_one_author_coalesce_until_someone_else_edits() {
+        let store = MemoryStore::new();
+        let (owner, member) = (ObjectId::new(), ObjectId::new());
+        let project = project(owner);
+        let contents = |html: &str| ProjectContentsResponse::from_html(html.to_string(), String::new());
+        let edit = RevisionSource::Edit { coalesce_since: expires_after(-Duration::minutes(1)) };
+
+        store.insert_project(&project).await.unwrap();
+        store.save_revision(project.id, owner, &contents("created"), RevisionSource::Created).await.unwrap();
+
+        let first = store.save_revision(project.id, owner, &contents("a"), edit).await.unwrap().unwrap();
+        let coalesced = store.save_revision(project.id, owner, &contents("b"), edit).await.unwrap().unwrap();
+        let other = store.save_revision(project.id, member, &contents("c"), edit).await.unwrap().unwrap();
+        let restored = store.save_revision(project.id, owner, &contents("created"), RevisionSource::Restore { sequence: 1 })
+            .await.unwrap().unwrap();
+
+        assert_eq!((first.sequence, coalesced.sequence, other.sequence, restored.sequence), (2, 2, 3, 4));
+        assert_eq!(coalesced.edits, 2);
+        assert_eq!((first.version, coalesced.version, other.version, restored.version), (2, 3, 4, 5));
+        assert_eq!(store.find_revision(project.id, 2).await.unwrap().unwrap().tier_container_html, "b");
+        assert_eq!(restored.restored_from, Some(1));
+
+        let project = store.find_project(project.id).await.unwrap().unwrap();
+        assert_eq!((project.revision, project.tier_container_html.as_str()), (4, "created"));
+
+        let sequences: Vec<i64> = store.project_revisions(project.id).await.unwrap().iter().map(|r| r.sequence).collect();
+        assert_eq!(sequences, vec![4, 3, 2, 1]);
+
+        store.trash_project(project.id, expires_after(-Duration::days(1))).await.unwrap();
+        assert!(store.purge_project(project.id, DateTime::now()).await.unwrap());
+        assert!(store.project_revisions(project.id).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,294 @@
+//! Persistence behind traits, so handlers work the same against MongoDB or memory.
+
+mod memory;
+mod mongo;
+
+pub use memory::MemoryStore;
+pub use mongo::MongoStore;
+
+use crate::authorization::ProjectRole;
+use crate::config::{Config, StorageBackend};
+use crate::error;
+use crate::models::{
+    EmailToken, EmailTokenPurpose, Invitation, InvitationStatus, InviteLink, Project, RefreshToken, Revision, Session,
+    User,
+};
+use crate::tier_list::TierList;
+use crate::ws_types::ProjectContentsResponse;
+use async_trait::async_trait;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use mongodb::Client;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    async fn find_user(&self, user_id: ObjectId) -> error::Result<Option<User>>;
+
+    async fn find_user_by_email(&self, email: &str) -> error::Result<Option<User>>;
+
+    /// Fails with `Conflict` if another account already uses the email.
+    async fn insert_user(&self, user: &User) -> error::Result<()>;
+
+    async fn set_user_verified(&self, user_id: ObjectId) -> error::Result<()>;
+
+    async fn set_password_hash(&self, user_id: ObjectId, passwd_hash: &str) -> error::Result<()>;
+
+    async fn add_project_to_users(&self, user_ids: &[ObjectId], project_id: ObjectId) -> error::Result<()>;
+}
+
+#[async_trait]
+pub trait ProjectStore: Send + Sync {
+    async fn find_project(&self, project_id: ObjectId) -> error::Result<Option<Project>>;
+
+    /// The projects among `project_ids` that were made from `template_link`, leaving out trashed ones.
+    async fn find_projects_by_template(
+        &self,
+        project_ids: &[ObjectId],
+        template_link: &str,
+    ) -> error::Result<Vec<Project>>;
+
+    async fn insert_project(&self, project: &Project) -> error::Result<()>;
+
+    async fn add_contributors(&self, project_id: ObjectId, user_ids: &[ObjectId]) -> error::Result<()>;
+
+    async fn set_member_role(&self, project_id: ObjectId, member_id: ObjectId, role: ProjectRole) -> error::Result<()>;
+
+    /// Adds a contributor together with their role, so neither is ever stored without the other.
+    async fn add_member_with_role(&self, project_id: ObjectId, member_id: ObjectId, role: ProjectRole) -> error::Result<()>;
+
+    /// Projects stored before tier lists existed, which still need theirs parsed from HTML.
+    async fn projects_without_tier_list(&self) -> error::Result<Vec<Project>>;
+
+    /// Stores a migrated tier list unless the project gained one meanwhile; returns whether it was stored.
+    async fn set_tier_list(&self, project_id: ObjectId, tier_list: &TierList) -> error::Result<bool>;
+
+    /// Moves a project into the trash; returns false if it is missing or already there.
+    async fn trash_project(&self, project_id: ObjectId, deleted_at: DateTime) -> error::Result<bool>;
+
+    /// Takes a project out of the trash; returns false if it is missing or not trashed.
+    async fn restore_project(&self, project_id: ObjectId) -> error::Result<bool>;
+
+    async fn trashed_projects(&self, owner_id: ObjectId) -> error::Result<Vec<Project>>;
+
+    async fn projects_trashed_before(&self, cutoff: DateTime) -> error::Result<Vec<ObjectId>>;
+
+    /// Deletes the project with its invitations, invite links and revisions, and drops it from every user's list,
+    /// provided it is still in the trash since before `cutoff`.
+    ///
+    /// A project restored meanwhile is left alone. All or nothing: a failure partway leaves the project and
+    /// everything that refers to it in place. Returns whether the project was purged.
+    async fn purge_project(&self, project_id: ObjectId, cutoff: DateTime) -> error::Result<bool>;
+}
+
+/// Why a revision is being saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RevisionSource {
+    /// The contents the project was created with.
+    Created,
+    /// A client edit, folded into the latest revision if the same author started it after `coalesce_since`.
+    Edit {
+        coalesce_since: DateTime,
+    },
+    /// A restore of the revision numbered `sequence`.
+    Restore {
+        sequence: i64,
+    },
+}
+
+#[async_trait]
+pub trait RevisionStore: Send + Sync {
+    /// Writes `contents` to the project and records them as a revision by `author`, both or neither.
+    ///
+    /// Returns the new or coalesced revision, or `None` if the project does not exist.
+    async fn save_revision(
+        &self,
+        project_id: ObjectId,
+        author: ObjectId,
+        contents: &ProjectContentsResponse,
+        source: RevisionSource,
+    ) -> error::Result<Option<Revision>>;
+
+    /// The project's revisions, newest first.
+    async fn project_revisions(&self, project_id: ObjectId) -> error::Result<Vec<Revision>>;
+
+    async fn find_revision(&self, project_id: ObjectId, sequence: i64) -> error::Result<Option<Revision>>;
+}
+
+/// Whether an edit by `author` from `source` should be folded into `latest` rather than start a new revision.
+///
+/// Only plain edits coalesce, so the created and restored states always stay reachable.
+fn coalesces_into(latest: &Revision, author: ObjectId, source: RevisionSource) -> bool {
+    match source {
+        RevisionSource::Edit { coalesce_since } => {
+            latest.author == author && latest.edits > 0 && latest.created_at > coalesce_since
+        }
+        RevisionSource::Created | RevisionSource::Restore { .. } => false,
+    }
+}
+
+fn absorb_edit(latest: &mut Revision, contents: &ProjectContentsResponse, version: i64, now: DateTime) {
+    latest.updated_at = now;
+    latest.version = version;
+    latest.edits += 1;
+    latest.tier_container_html = contents.tier_container_html.clone();
+    latest.image_carousel_html = contents.image_carousel_html.clone();
+    latest.tier_list = Some(contents.tier_list.clone());
+}
+
+/// The revision for `project` as just saved, numbered with its `revision` counter.
+fn new_revision(
+    project: &Project,
+    author: ObjectId,
+    contents: &ProjectContentsResponse,
+    source: RevisionSource,
+    now: DateTime,
+) -> Revision {
+    Revision {
+        id: ObjectId::new(),
+        project_id: project.id,
+        sequence: project.revision,
+        author,
+        created_at: now,
+        updated_at: now,
+        edits: if let RevisionSource::Edit { .. } = source { 1 } else { 0 },
+        tier_container_html: contents.tier_container_html.clone(),
+        image_carousel_html: contents.image_carousel_html.clone(),
+        tier_list: Some(contents.tier_list.clone()),
+        restored_from: if let RevisionSource::Restore { sequence } = source { Some(sequence) } else { None },
+        version: project.version,
+    }
+}
+
+#[async_trait]
+pub trait InvitationStore: Send + Sync {
+    async fn count_invitations_since(&self, inviter_id: ObjectId, since: DateTime) -> error::Result<u64>;
+
+    /// Stores `invitation` unless the same email already has an open one for the project.
+    ///
+    /// Returns whether a new invitation was stored.
+    async fn create_invitation(&self, invitation: &Invitation) -> error::Result<bool>;
+
+    /// Marks every pending invitation past its expiry as expired.
+    async fn expire_invitations(&self) -> error::Result<()>;
+
+    async fn pending_invitations_for_project(&self, project_id: ObjectId) -> error::Result<Vec<Invitation>>;
+
+    async fn pending_invitations_for_email(&self, email: &str) -> error::Result<Vec<Invitation>>;
+
+    /// Deletes the pending invitations for `email` on a project and returns how many there were.
+    async fn delete_pending_invitations(&self, project_id: ObjectId, email: &str) -> error::Result<u64>;
+
+    /// Moves an unexpired pending invitation addressed to `email` to `status`.
+    async fn respond_to_invitation(
+        &self,
+        invitation_id: ObjectId,
+        email: &str,
+        status: InvitationStatus,
+    ) -> error::Result<Option<Invitation>>;
+
+    async fn find_invitation(&self, invitation_id: ObjectId) -> error::Result<Option<Invitation>>;
+}
+
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    async fn insert_session(&self, session: &Session) -> error::Result<()>;
+
+    /// The session, if it belongs to `user_id` and has not been revoked.
+    async fn find_active_session(&self, user_id: ObjectId, session_id: ObjectId) -> error::Result<Option<Session>>;
+
+    async fn active_sessions(&self, user_id: ObjectId) -> error::Result<Vec<Session>>;
+
+    async fn touch_session(&self, session_id: ObjectId) -> error::Result<()>;
+
+    async fn revoke_session(&self, session_id: ObjectId) -> error::Result<()>;
+
+    async fn insert_refresh_token(&self, token: &RefreshToken) -> error::Result<()>;
+
+    /// Marks an unused, unrevoked token as used and returns it.
+    ///
+    /// This must be atomic so two concurrent refreshes cannot both rotate one token.
+    async fn claim_refresh_token(&self, token_hash: &str) -> error::Result<Option<RefreshToken>>;
+
+    async fn find_refresh_token(&self, token_hash: &str) -> error::Result<Option<RefreshToken>>;
+
+    async fn revoke_refresh_token_family(&self, family_id: ObjectId) -> error::Result<()>;
+}
+
+#[async_trait]
+pub trait EmailTokenStore: Send + Sync {
+    async fn insert_email_token(&self, purpose: EmailTokenPurpose, token: &EmailToken) -> error::Result<()>;
+
+    async fn count_email_tokens_since(
+        &self,
+        purpose: EmailTokenPurpose,
+        user_id: ObjectId,
+        since: DateTime,
+    ) -> error::Result<u64>;
+
+    /// Marks every unused token the user has for `purpose` as used.
+    async fn use_up_email_tokens(&self, purpose: EmailTokenPurpose, user_id: ObjectId) -> error::Result<()>;
+
+    /// Marks an unused, unexpired token as used and returns it, so each code works once.
+    async fn consume_email_token(
+        &self,
+        purpose: EmailTokenPurpose,
+        token_hash: &str,
+    ) -> error::Result<Option<EmailToken>>;
+}
+
+#[async_trait]
+pub trait InviteLinkStore: Send + Sync {
+    async fn insert_invite_link(&self, link: &InviteLink) -> error::Result<()>;
+
+    async fn usable_invite_links(&self, project_id: ObjectId) -> error::Result<Vec<InviteLink>>;
+
+    /// Returns whether the link existed on that project.
+    async fn revoke_invite_link(&self, project_id: ObjectId, link_id: ObjectId) -> error::Result<bool>;
+
+    async fn find_usable_invite_link(&self, token_hash: &str) -> error::Result<Option<InviteLink>>;
+
+    /// Counts one use of the link if it is still usable and returns it.
+    ///
+    /// The check and the increment happen together, which keeps `max_uses` exact.
+    async fn use_invite_link(&self, token_hash: &str) -> error::Result<Option<InviteLink>>;
+}
+
+pub trait Store:
+    UserStore + ProjectStore + RevisionStore + InvitationStore + SessionStore + EmailTokenStore + InviteLinkStore
+{}
+
+impl<T> Store for T
+where
+    T: UserStore + ProjectStore + RevisionStore + InvitationStore + SessionStore + EmailTokenStore + InviteLinkStore,
+{}
+
+#[derive(Error, Debug)]
+pub enum OpenError {
+    #[error(transparent)]
+    Mongo(#[from] mongodb::error::Error),
+    #[error("MONGODB_URI points at a standalone server, but transactions need a replica set (one member will do)")]
+    TransactionsUnsupported,
+}
+
+/// Opens the backend `config` asks for.
+pub async fn open(config: &Config) -> Result<Arc<dyn Store>, OpenError> {
+    match config.storage_backend {
+        StorageBackend::Memory => Ok(Arc::new(MemoryStore::new())),
+        StorageBackend::Mongo => {
+            let uri = config.mongodb_uri.as_deref().unwrap_or_default();
+            let client = Client::with_uri_str(uri).await?;
+
+            let store = MongoStore::new(client.database(&config.database_name));
+
+            if !store.supports_transactions().await? {
+                return Err(OpenError::TransactionsUnsupported);
+            }
+
+            store.create_indexes().await?;
+
+            Ok(Arc::new(store))
+        }
+    }
+}
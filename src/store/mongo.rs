@@ -0,0 +1,704 @@
+use super::{
+    absorb_edit, coalesces_into, new_revision, EmailTokenStore, InvitationStore, InviteLinkStore, ProjectStore,
+    RevisionSource, RevisionStore, SessionStore, UserStore,
+};
+use crate::authorization::ProjectRole;
+use crate::db_constants::{
+    Collections, EmailTokenFields, InvitationFields, InviteLinkFields,
+    ProjectFields, RefreshTokenFields, RevisionFields, SessionFields, UserFields,
+};
+use crate::error;
+use crate::error::SharedTierListError::Conflict;
+use crate::models::{
+    EmailToken, EmailTokenPurpose, Invitation, InvitationStatus, InviteLink, Project, RefreshToken, Revision, Session,
+    User,
+};
+use crate::tier_list::TierList;
+use crate::ws_types::ProjectContentsResponse;
+use async_trait::async_trait;
+use futures_util::future::BoxFuture;
+use futures_util::{FutureExt, TryStreamExt};
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::{doc, to_bson, DateTime, Document};
+use mongodb::error::{ErrorKind, WriteFailure, TRANSIENT_TRANSACTION_ERROR};
+use mongodb::options::{IndexOptions, ReturnDocument};
+use mongodb::{ClientSession, Collection, Database, IndexModel};
+
+/// How often a transaction that lost a write conflict to a concurrent one is run again before giving up.
+const MAX_TRANSACTION_ATTEMPTS: u32 = 3;
+
+/// The server's code for a write that broke a unique index.
+const DUPLICATE_KEY: i32 = 11000;
+
+/// The production store, backed by MongoDB.
+#[derive(Clone)]
+pub struct MongoStore {
+    db: Database,
+}
+
+impl MongoStore {
+    pub fn new(db: Database) -> MongoStore {
+        MongoStore {
+            db,
+        }
+    }
+
+    /// Whether the server runs transactions, which only replica set members and mongos routers do; a standalone
+    /// server rejects them.
+    pub async fn supports_transactions(&self) -> mongodb::error::Result<bool> {
+        let hello = self.db.run_command(doc! { "hello": 1 }).await?;
+
+        Ok(hello.contains_key("setName") || hello.get_str("msg") == Ok("isdbgrid"))
+    }
+
+    /// Creates the unique indexes the store relies on to keep concurrent writes from making duplicates.
+    pub async fn create_indexes(&self) -> mongodb::error::Result<()> {
+        self.users().create_index(
+            IndexModel::builder()
+                .keys(doc! { UserFields::EMAIL: 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build()
+        ).await?;
+
+        self.invitations().create_index(
+            IndexModel::builder()
+                .keys(doc! { InvitationFields::PROJECT_ID: 1, InvitationFields::EMAIL: 1 })
+                .options(IndexOptions::builder()
+                    .unique(true)
+                    .partial_filter_expression(doc! { InvitationFields::STATUS: InvitationStatus::Pending.as_str() })
+                    .build())
+                .build()
+        ).await?;
+
+        Ok(())
+    }
+
+    fn users(&self) -> Collection<User> {
+        self.db.collection(Collections::USERS)
+    }
+
+    fn projects(&self) -> Collection<Project> {
+        self.db.collection(Collections::PROJECTS)
+    }
+
+    fn revisions(&self) -> Collection<Revision> {
+        self.db.collection(Collections::REVISIONS)
+    }
+
+    fn invitations(&self) -> Collection<Invitation> {
+        self.db.collection(Collections::INVITATIONS)
+    }
+
+    fn sessions(&self) -> Collection<Session> {
+        self.db.collection(Collections::SESSIONS)
+    }
+
+    fn refresh_tokens(&self) -> Collection<RefreshToken> {
+        self.db.collection(Collections::REFRESH_TOKENS)
+    }
+
+    fn email_tokens(&self, purpose: EmailTokenPurpose) -> Collection<EmailToken> {
+        match purpose {
+            EmailTokenPurpose::PasswordReset => self.db.collection(Collections::PASSWORD_RESETS),
+            EmailTokenPurpose::EmailVerification => self.db.collection(Collections::EMAIL_VERIFICATIONS),
+        }
+    }
+
+    fn invite_links(&self) -> Collection<InviteLink> {
+        self.db.collection(Collections::INVITE_LINKS)
+    }
+
+    /// Runs `run` in a transaction, committing if it succeeds. `store::open` refuses servers that cannot run one.
+    async fn in_transaction<C: Sync, R>(
+        &self,
+        context: C,
+        run: for<'s> fn(&'s MongoStore, &'s mut ClientSession, &'s C) -> BoxFuture<'s, error::Result<R>>,
+    ) -> error::Result<R> {
+        let mut session = self.db.client().start_session().await?;
+        let mut attempt = 1;
+
+        loop {
+            session.start_transaction().await?;
+
+            let result = match run(self, &mut session, &context).await {
+                Ok(value) => session.commit_transaction().await.map(|()| value).map_err(Into::into),
+                Err(e) => {
+                    let _ = session.abort_transaction().await;
+                    Err(e)
+                }
+            };
+
+            match result {
+                Err(e) if is_transient(&e) && attempt < MAX_TRANSACTION_ATTEMPTS => attempt += 1,
+                result => return result,
+            }
+        }
+    }
+
+    async fn purge_project_in(
+        &self,
+        session: &mut ClientSession,
+        project_id: ObjectId,
+        cutoff: DateTime,
+    ) -> error::Result<bool> {
+        // Checking the cutoff in the delete leaves a project restored meanwhile alone, and a restore racing
+        // this transaction waits for it and then finds nothing to restore.
+        let deleted = self.projects()
+            .delete_one(doc! { ProjectFields::ID: project_id, ProjectFields::DELETED_AT: { "$lt": cutoff } })
+            .session(&mut *session)
+            .await?;
+
+        if deleted.deleted_count == 0 {
+            return Ok(false);
+        }
+
+        self.invitations()
+            .delete_many(doc! { InvitationFields::PROJECT_ID: project_id })
+            .session(&mut *session)
+            .await?;
+
+        self.invite_links()
+            .delete_many(doc! { InviteLinkFields::PROJECT_ID: project_id })
+            .session(&mut *session)
+            .await?;
+
+        self.revisions()
+            .delete_many(doc! { RevisionFields::PROJECT_ID: project_id })
+            .session(&mut *session)
+            .await?;
+
+        // Matching on the array rather than the member list also catches anyone who joined meanwhile.
+        self.users()
+            .update_many(
+                doc! { UserFields::PROJECTS: project_id },
+                doc! { "$pull": { UserFields::PROJECTS: project_id } }
+            )
+            .session(&mut *session)
+            .await?;
+
+        Ok(true)
+    }
+
+    async fn save_revision_in(
+        &self,
+        session: &mut ClientSession,
+        project_id: ObjectId,
+        author: ObjectId,
+        contents: &ProjectContentsResponse,
+        source: RevisionSource,
+    ) -> error::Result<Option<Revision>> {
+        let now = DateTime::now();
+
+        let latest_opt = self.revisions()
+            .find_one(doc! { RevisionFields::PROJECT_ID: project_id })
+            .sort(doc! { RevisionFields::SEQUENCE: -1 })
+            .session(&mut *session)
+            .await?
+            .filter(|latest| coalesces_into(latest, author, source));
+
+        let mut update = doc! { "$set": {
+            ProjectFields::TIER_CONTAINER_HTML: &contents.tier_container_html,
+            ProjectFields::IMAGE_CAROUSEL_HTML: &contents.image_carousel_html,
+            ProjectFields::TIER_LIST: to_bson(&contents.tier_list)?,
+        } };
+
+        // Bumping the counter on the project hands out each sequence number once, even to concurrent edits.
+        // It also makes every save write the project, so two saves that read the same latest revision
+        // conflict and the later one runs again instead of overwriting the coalesced revision.
+        let mut counters = doc! { ProjectFields::VERSION: 1 };
+        if latest_opt.is_none() {
+            counters.insert(ProjectFields::REVISION, 1);
+        }
+        update.insert("$inc", counters);
+
+        let project_opt = self.projects()
+            .find_one_and_update(doc! { ProjectFields::ID: project_id }, update)
+            .return_document(ReturnDocument::After)
+            .session(&mut *session)
+            .await?;
+
+        let Some(project) = project_opt else {
+            return Ok(None);
+        };
+
+        let revision = match latest_opt {
+            Some(mut latest) => {
+                absorb_edit(&mut latest, contents, project.version, now);
+                self.revisions()
+                    .replace_one(doc! { RevisionFields::ID: latest.id }, &latest)
+                    .session(&mut *session)
+                    .await?;
+                latest
+            }
+            None => {
+                let revision = new_revision(&project, author, contents, source, now);
+                self.revisions().insert_one(&revision).session(&mut *session).await?;
+                revision
+            }
+        };
+
+        Ok(Some(revision))
+    }
+}
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(&*e.kind, ErrorKind::Write(WriteFailure::WriteError(write_error)) if write_error.code == DUPLICATE_KEY)
+}
+
+/// A write conflict with a concurrent transaction, or a failover, which running the transaction again can fix.
+fn is_transient(e: &error::SharedTierListError) -> bool {
+    matches!(e, error::SharedTierListError::MongoError(e) if e.contains_label(TRANSIENT_TRANSACTION_ERROR))
+}
+
+/// Matches links that are not revoked, not expired and not used up.
+fn usable_link_filter() -> Document {
+    doc! {
+        InviteLinkFields::REVOKED: false,
+        "$and": [
+            { "$or": [
+                { InviteLinkFields::EXPIRES_AT: null },
+                { InviteLinkFields::EXPIRES_AT: { "$gt": DateTime::now() } },
+            ] },
+            { "$or": [
+                { InviteLinkFields::MAX_USES: null },
+                { "$expr": { "$lt": [
+                    format!("${}", InviteLinkFields::USES),
+                    format!("${}", InviteLinkFields::MAX_USES),
+                ] } },
+            ] },
+        ],
+    }
+}
+
+#[async_trait]
+impl UserStore for MongoStore {
+    async fn find_user(&self, user_id: ObjectId) -> error::Result<Option<User>> {
+        Ok(self.users().find_one(doc! { UserFields::ID: user_id }).await?)
+    }
+
+    async fn find_user_by_email(&self, email: &str) -> error::Result<Option<User>> {
+        Ok(self.users().find_one(doc! { UserFields::EMAIL: email }).await?)
+    }
+
+    async fn insert_user(&self, user: &User) -> error::Result<()> {
+        match self.users().insert_one(user).await {
+            Ok(_) => Ok(()),
+            Err(e) if is_duplicate_key(&e) => Err(Conflict("An account with this email already exists".to_string())),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn set_user_verified(&self, user_id: ObjectId) -> error::Result<()> {
+        self.users().update_one(
+            doc! { UserFields::ID: user_id },
+            doc! { "$set": { UserFields::VERIFIED: true } }
+        ).await?;
+        Ok(())
+    }
+
+    async fn set_password_hash(&self, user_id: ObjectId, passwd_hash: &str) -> error::Result<()> {
+        self.users().update_one(
+            doc! { UserFields::ID: user_id },
+            doc! { "$set": { UserFields::PASSWD_HASH: passwd_hash } }
+        ).await?;
+        Ok(())
+    }
+
+    async fn add_project_to_users(&self, user_ids: &[ObjectId], project_id: ObjectId) -> error::Result<()> {
+        self.users().update_many(
+            doc! { UserFields::ID: { "$in": user_ids } },
+            doc! { "$addToSet": { UserFields::PROJECTS: project_id } }
+        ).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ProjectStore for MongoStore {
+    async fn find_project(&self, project_id: ObjectId) -> error::Result<Option<Project>> {
+        Ok(self.projects().find_one(doc! { ProjectFields::ID: project_id }).await?)
+    }
+
+    async fn find_projects_by_template(
+        &self,
+        project_ids: &[ObjectId],
+        template_link: &str,
+    ) -> error::Result<Vec<Project>> {
+        Ok(self.projects().find(doc! {
+            ProjectFields::ID: { "$in": project_ids },
+            ProjectFields::TEMPLATE_LINK: template_link,
+            ProjectFields::DELETED_AT: null,
+        }).await?.try_collect().await?)
+    }
+
+    async fn insert_project(&self, project: &Project) -> error::Result<()> {
+        self.projects().insert_one(project).await?;
+        Ok(())
+    }
+
+    async fn add_contributors(&self, project_id: ObjectId, user_ids: &[ObjectId]) -> error::Result<()> {
+        self.projects().update_one(
+            doc! { ProjectFields::ID: project_id },
+            doc! { "$addToSet": { ProjectFields::CONTRIBUTORS: { "$each": user_ids } } }
+        ).await?;
+        Ok(())
+    }
+
+    async fn set_member_role(&self, project_id: ObjectId, member_id: ObjectId, role: ProjectRole) -> error::Result<()> {
+        self.projects().update_one(
+            doc! { ProjectFields::ID: project_id },
+            doc! { "$set": {
+                format!("{}.{}", ProjectFields::MEMBER_ROLES, member_id.to_hex()): role.as_str()
+            } }
+        ).await?;
+        Ok(())
+    }
+
+    async fn add_member_with_role(&self, project_id: ObjectId, member_id: ObjectId, role: ProjectRole) -> error::Result<()> {
+        self.projects().update_one(
+            doc! { ProjectFields::ID: project_id },
+            doc! {
+                "$addToSet": { ProjectFields::CONTRIBUTORS: member_id },
+                "$set": { format!("{}.{}", ProjectFields::MEMBER_ROLES, member_id.to_hex()): role.as_str() },
+            }
+        ).await?;
+        Ok(())
+    }
+
+    async fn projects_without_tier_list(&self) -> error::Result<Vec<Project>> {
+        Ok(self.projects()
+            .find(doc! { ProjectFields::TIER_LIST: null })
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn set_tier_list(&self, project_id: ObjectId, tier_list: &TierList) -> error::Result<bool> {
+        let updated = self.projects().update_one(
+            doc! { ProjectFields::ID: project_id, ProjectFields::TIER_LIST: null },
+            doc! { "$set": { ProjectFields::TIER_LIST: to_bson(tier_list)? } }
+        ).await?;
+
+        Ok(updated.matched_count > 0)
+    }
+
+    async fn trash_project(&self, project_id: ObjectId, deleted_at: DateTime) -> error::Result<bool> {
+        let trashed = self.projects().update_one(
+            doc! { ProjectFields::ID: project_id, ProjectFields::DELETED_AT: null },
+            doc! { "$set": { ProjectFields::DELETED_AT: deleted_at } }
+        ).await?;
+
+        Ok(trashed.matched_count > 0)
+    }
+
+    async fn restore_project(&self, project_id: ObjectId) -> error::Result<bool> {
+        let restored = self.projects().update_one(
+            doc! { ProjectFields::ID: project_id, ProjectFields::DELETED_AT: { "$ne": null } },
+            doc! { "$unset": { ProjectFields::DELETED_AT: "" } }
+        ).await?;
+
+        Ok(restored.matched_count > 0)
+    }
+
+    async fn trashed_projects(&self, owner_id: ObjectId) -> error::Result<Vec<Project>> {
+        Ok(self.projects().find(doc! {
+            ProjectFields::OWNER: owner_id,
+            ProjectFields::DELETED_AT: { "$ne": null },
+        }).await?.try_collect().await?)
+    }
+
+    async fn projects_trashed_before(&self, cutoff: DateTime) -> error::Result<Vec<ObjectId>> {
+        let projects: Vec<Project> = self.projects()
+            .find(doc! { ProjectFields::DELETED_AT: { "$lt": cutoff } })
+            .await?
+            .try_collect()
+            .await?;
+
+        Ok(projects.into_iter().map(|project| project.id).collect())
+    }
+
+    async fn purge_project(&self, project_id: ObjectId, cutoff: DateTime) -> error::Result<bool> {
+        self.in_transaction((project_id, cutoff), |store, session, &(project_id, cutoff)| {
+            store.purge_project_in(session, project_id, cutoff).boxed()
+        }).await
+    }
+}
+
+#[async_trait]
+impl RevisionStore for MongoStore {
+    async fn save_revision(
+        &self,
+        project_id: ObjectId,
+        author: ObjectId,
+        contents: &ProjectContentsResponse,
+        source: RevisionSource,
+    ) -> error::Result<Option<Revision>> {
+        self.in_transaction((project_id, author, contents, source), |store, session, &(project_id, author, contents, source)| {
+            store.save_revision_in(session, project_id, author, contents, source).boxed()
+        }).await
+    }
+
+    async fn project_revisions(&self, project_id: ObjectId) -> error::Result<Vec<Revision>> {
+        Ok(self.revisions()
+            .find(doc! { RevisionFields::PROJECT_ID: project_id })
+            .sort(doc! { RevisionFields::SEQUENCE: -1 })
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn find_revision(&self, project_id: ObjectId, sequence: i64) -> error::Result<Option<Revision>> {
+        Ok(self.revisions().find_one(doc! {
+            RevisionFields::PROJECT_ID: project_id,
+            RevisionFields::SEQUENCE: sequence,
+        }).await?)
+    }
+}
+
+#[async_trait]
+impl InvitationStore for MongoStore {
+    async fn count_invitations_since(&self, inviter_id: ObjectId, since: DateTime) -> error::Result<u64> {
+        Ok(self.invitations().count_documents(doc! {
+            InvitationFields::INVITED_BY: inviter_id,
+            InvitationFields::CREATED_AT: { "$gt": since },
+        }).await?)
+    }
+
+    async fn create_invitation(&self, invitation: &Invitation) -> error::Result<bool> {
+        // Upserting against the open invitation keeps re-invites from stacking duplicates. The unique index
+        // turns a concurrent upsert of the same invitation into a duplicate key error.
+        let upsert_result = self.invitations().update_one(
+            doc! {
+                InvitationFields::EMAIL: &invitation.email,
+                InvitationFields::PROJECT_ID: invitation.project_id,
+                InvitationFields::STATUS: InvitationStatus::Pending.as_str(),
+                InvitationFields::EXPIRES_AT: { "$gt": DateTime::now() },
+            },
+            doc! { "$setOnInsert": {
+                InvitationFields::ID: invitation.id,
+                InvitationFields::INVITED_BY: invitation.invited_by,
+                InvitationFields::CREATED_AT: invitation.created_at,
+                InvitationFields::EXPIRES_AT: invitation.expires_at,
+            } }
+        ).upsert(true).await;
+
+        match upsert_result {
+            Ok(upsert) => Ok(upsert.upserted_id.is_some()),
+            Err(e) if is_duplicate_key(&e) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn expire_invitations(&self) -> error::Result<()> {
+        self.invitations().update_many(
+            doc! {
+                InvitationFields::STATUS: InvitationStatus::Pending.as_str(),
+                InvitationFields::EXPIRES_AT: { "$lte": DateTime::now() },
+            },
+            doc! { "$set": { InvitationFields::STATUS: InvitationStatus::Expired.as_str() } }
+        ).await?;
+        Ok(())
+    }
+
+    async fn pending_invitations_for_project(&self, project_id: ObjectId) -> error::Result<Vec<Invitation>> {
+        Ok(self.invitations().find(doc! {
+            InvitationFields::PROJECT_ID: project_id,
+            InvitationFields::STATUS: InvitationStatus::Pending.as_str(),
+        }).await?.try_collect().await?)
+    }
+
+    async fn pending_invitations_for_email(&self, email: &str) -> error::Result<Vec<Invitation>> {
+        Ok(self.invitations().find(doc! {
+            InvitationFields::EMAIL: email,
+            InvitationFields::STATUS: InvitationStatus::Pending.as_str(),
+        }).await?.try_collect().await?)
+    }
+
+    async fn delete_pending_invitations(&self, project_id: ObjectId, email: &str) -> error::Result<u64> {
+        let deleted = self.invitations().delete_many(doc! {
+            InvitationFields::EMAIL: email,
+            InvitationFields::PROJECT_ID: project_id,
+            InvitationFields::STATUS: InvitationStatus::Pending.as_str(),
+        }).await?;
+
+        Ok(deleted.deleted_count)
+    }
+
+    async fn respond_to_invitation(
+        &self,
+        invitation_id: ObjectId,
+        email: &str,
+        status: InvitationStatus,
+    ) -> error::Result<Option<Invitation>> {
+        Ok(self.invitations().find_one_and_update(
+            doc! {
+                InvitationFields::ID: invitation_id,
+                InvitationFields::EMAIL: email,
+                InvitationFields::STATUS: InvitationStatus::Pending.as_str(),
+                InvitationFields::EXPIRES_AT: { "$gt": DateTime::now() },
+            },
+            doc! { "$set": {
+                InvitationFields::STATUS: status.as_str(),
+                InvitationFields::RESPONDED_AT: DateTime::now(),
+            } }
+        ).await?)
+    }
+
+    async fn find_invitation(&self, invitation_id: ObjectId) -> error::Result<Option<Invitation>> {
+        Ok(self.invitations().find_one(doc! { InvitationFields::ID: invitation_id }).await?)
+    }
+}
+
+#[async_trait]
+impl SessionStore for MongoStore {
+    async fn insert_session(&self, session: &Session) -> error::Result<()> {
+        self.sessions().insert_one(session).await?;
+        Ok(())
+    }
+
+    async fn find_active_session(&self, user_id: ObjectId, session_id: ObjectId) -> error::Result<Option<Session>> {
+        Ok(self.sessions().find_one(doc! {
+            SessionFields::ID: session_id,
+            SessionFields::USER_ID: user_id,
+            SessionFields::REVOKED: false,
+        }).await?)
+    }
+
+    async fn active_sessions(&self, user_id: ObjectId) -> error::Result<Vec<Session>> {
+        Ok(self.sessions().find(doc! {
+            SessionFields::USER_ID: user_id,
+            SessionFields::REVOKED: false,
+        }).await?.try_collect().await?)
+    }
+
+    async fn touch_session(&self, session_id: ObjectId) -> error::Result<()> {
+        self.sessions().update_one(
+            doc! { SessionFields::ID: session_id },
+            doc! { "$set": { SessionFields::LAST_REFRESHED_AT: DateTime::now() } }
+        ).await?;
+        Ok(())
+    }
+
+    async fn revoke_session(&self, session_id: ObjectId) -> error::Result<()> {
+        self.sessions().update_one(
+            doc! { SessionFields::ID: session_id },
+            doc! { "$set": { SessionFields::REVOKED: true } }
+        ).await?;
+        Ok(())
+    }
+
+    async fn insert_refresh_token(&self, token: &RefreshToken) -> error::Result<()> {
+        self.refresh_tokens().insert_one(token).await?;
+        Ok(())
+    }
+
+    async fn claim_refresh_token(&self, token_hash: &str) -> error::Result<Option<RefreshToken>> {
+        Ok(self.refresh_tokens().find_one_and_update(
+            doc! {
+                RefreshTokenFields::TOKEN_HASH: token_hash,
+                RefreshTokenFields::USED: false,
+                RefreshTokenFields::REVOKED: false,
+            },
+            doc! { "$set": { RefreshTokenFields::USED: true } }
+        ).await?)
+    }
+
+    async fn find_refresh_token(&self, token_hash: &str) -> error::Result<Option<RefreshToken>> {
+        Ok(self.refresh_tokens().find_one(doc! { RefreshTokenFields::TOKEN_HASH: token_hash }).await?)
+    }
+
+    async fn revoke_refresh_token_family(&self, family_id: ObjectId) -> error::Result<()> {
+        self.refresh_tokens().update_many(
+            doc! { RefreshTokenFields::FAMILY_ID: family_id },
+            doc! { "$set": { RefreshTokenFields::REVOKED: true } }
+        ).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EmailTokenStore for MongoStore {
+    async fn insert_email_token(&self, purpose: EmailTokenPurpose, token: &EmailToken) -> error::Result<()> {
+        self.email_tokens(purpose).insert_one(token).await?;
+        Ok(())
+    }
+
+    async fn count_email_tokens_since(
+        &self,
+        purpose: EmailTokenPurpose,
+        user_id: ObjectId,
+        since: DateTime,
+    ) -> error::Result<u64> {
+        Ok(self.email_tokens(purpose).count_documents(doc! {
+            EmailTokenFields::USER_ID: user_id,
+            EmailTokenFields::CREATED_AT: { "$gt": since },
+        }).await?)
+    }
+
+    async fn use_up_email_tokens(&self, purpose: EmailTokenPurpose, user_id: ObjectId) -> error::Result<()> {
+        self.email_tokens(purpose).update_many(
+            doc! { EmailTokenFields::USER_ID: user_id, EmailTokenFields::USED: false },
+            doc! { "$set": { EmailTokenFields::USED: true } }
+        ).await?;
+        Ok(())
+    }
+
+    async fn consume_email_token(
+        &self,
+        purpose: EmailTokenPurpose,
+        token_hash: &str,
+    ) -> error::Result<Option<EmailToken>> {
+        Ok(self.email_tokens(purpose).find_one_and_update(
+            doc! {
+                EmailTokenFields::TOKEN_HASH: token_hash,
+                EmailTokenFields::USED: false,
+                EmailTokenFields::EXPIRES_AT: { "$gt": DateTime::now() },
+            },
+            doc! { "$set": { EmailTokenFields::USED: true } }
+        ).await?)
+    }
+}
+
+#[async_trait]
+impl InviteLinkStore for MongoStore {
+    async fn insert_invite_link(&self, link: &InviteLink) -> error::Result<()> {
+        self.invite_links().insert_one(link).await?;
+        Ok(())
+    }
+
+    async fn usable_invite_links(&self, project_id: ObjectId) -> error::Result<Vec<InviteLink>> {
+        let mut filter = usable_link_filter();
+        filter.insert(InviteLinkFields::PROJECT_ID, project_id);
+
+        Ok(self.invite_links().find(filter).await?.try_collect().await?)
+    }
+
+    async fn revoke_invite_link(&self, project_id: ObjectId, link_id: ObjectId) -> error::Result<bool> {
+        let revoked = self.invite_links().update_one(
+            doc! {
+                InviteLinkFields::ID: link_id,
+                InviteLinkFields::PROJECT_ID: project_id,
+            },
+            doc! { "$set": { InviteLinkFields::REVOKED: true } }
+        ).await?;
+
+        Ok(revoked.matched_count > 0)
+    }
+
+    async fn find_usable_invite_link(&self, token_hash: &str) -> error::Result<Option<InviteLink>> {
+        let mut filter = usable_link_filter();
+        filter.insert(InviteLinkFields::TOKEN_HASH, token_hash);
+
+        Ok(self.invite_links().find_one(filter).await?)
+    }
+
+    async fn use_invite_link(&self, token_hash: &str) -> error::Result<Option<InviteLink>> {
+        let mut filter = usable_link_filter();
+        filter.insert(InviteLinkFields::TOKEN_HASH, token_hash);
+
+        Ok(self.invite_links().find_one_and_update(
+            filter,
+            doc! { "$inc": { InviteLinkFields::USES: 1i64 } }
+        ).await?)
+    }
+}
@@ -0,0 +1,23 @@
+//! Documents for unit tests with every field filled in, so tests only spell out the fields they are about.
+
+use crate::models::Project;
+use mongodb::bson::oid::ObjectId;
+use std::collections::HashMap;
+
+/// An empty, live project owned by `owner` with no members.
+pub fn project(owner: ObjectId) -> Project {
+    Project {
+        id: ObjectId::new(),
+        name: "Project".to_string(),
+        template_link: "template".to_string(),
+        owner,
+        contributors: vec![],
+        member_roles: HashMap::new(),
+        tier_container_html: String::new(),
+        image_carousel_html: String::new(),
+        tier_list: None,
+        revision: 0,
+        version: 0,
+        deleted_at: None,
+    }
+}
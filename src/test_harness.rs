@@ -0,0 +1,275 @@
+//! Runs the server as `main` builds it, on a local port against an in-memory store, with HTTP and WebSocket clients.
+
+use crate::build_app;
+use crate::config::{Config, StorageBackend, MIN_JWT_SECRET_LEN};
+use crate::email_verification::VerificationPolicy;
+use crate::store::MemoryStore;
+use futures_util::{SinkExt, StreamExt};
+use http::{header, HeaderValue, StatusCode};
+use mongodb::bson::oid::ObjectId;
+use serde_json::{json, Value};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+const PASSWORD: &str = "correct horse battery staple";
+const RECV_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The directory the server's `FileMailer` writes to, so tests can read the codes a user would get.
+pub struct Outbox {
+    dir: PathBuf,
+}
+
+impl Outbox {
+    fn new() -> Outbox {
+        Outbox {
+            dir: std::env::temp_dir().join(format!("shared-tier-list-outbox-{}", ObjectId::new())),
+        }
+    }
+
+    /// Every mail sent to `email`, oldest first.
+    pub fn mails_to(&self, email: &str) -> Vec<String> {
+        let to = format!("To: {email}\n");
+
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return vec![];
+        };
+
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .collect();
+
+        // File names start with the send time, so they sort oldest first.
+        names.sort();
+
+        names.iter()
+            .filter_map(|name| fs::read_to_string(self.dir.join(name)).ok())
+            .filter(|contents| contents.starts_with(&to))
+            .collect()
+    }
+
+    /// The code from the latest mail sent to `email`.
+    pub fn last_code_for(&self, email: &str) -> Option<String> {
+        self.mails_to(email).last()?
+            .split_whitespace()
+            .find(|word| word.len() == 64 && word.chars().all(|c| c.is_ascii_hexdigit()))
+            .map(str::to_string)
+    }
+}
+
+impl Drop for Outbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A signed-in account.
+pub struct TestUser {
+    pub email: String,
+    pub token: String,
+    pub refresh_token: String,
+}
+
+pub struct TestApp {
+    address: SocketAddr,
+    client: reqwest::Client,
+    pub mailer: Outbox,
+}
+
+impl TestApp {
+    pub async fn spawn() -> TestApp {
+        TestApp::spawn_with_policy(VerificationPolicy::default()).await
+    }
+
+    /// Starts the server the way `main` does, from a config for the in-memory store.
+    pub async fn spawn_with_policy(verification_policy: VerificationPolicy) -> TestApp {
+        let mailer = Outbox::new();
+
+        let config = Config {
+            bind_address: SocketAddr::from(([127, 0, 0, 1], 0)),
+            storage_backend: StorageBackend::Memory,
+            jwt_secret_key: "t".repeat(MIN_JWT_SECRET_LEN),
+            mail_outbox_dir: Some(mailer.dir.clone()),
+            verification: verification_policy,
+            broadcast_channel_capacity: 16,
+            ..Config::default()
+        };
+        config.validate().unwrap();
+
+        let app = build_app(&config, Arc::new(MemoryStore::new())).await.unwrap();
+
+        let listener = tokio::net::TcpListener::bind(config.bind_address).await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        TestApp {
+            address,
+            client: reqwest::Client::new(),
+            mailer,
+        }
+    }
+
+    /// Posts `body` as JSON and returns the status with the parsed body, or `Null` when empty.
+    pub async fn post(&self, path: &str, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+        self.post_raw(path, token, body.to_string()).await
+    }
+
+    /// Like `post`, but sends `body` verbatim, so tests can send malformed JSON.
+    pub async fn post_raw(&self, path: &str, token: Option<&str>, body: String) -> (StatusCode, Value) {
+        let mut request = self.client.post(format!("http://{}{path}", self.address))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body);
+
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await.unwrap();
+        let status = response.status();
+        let text = response.text().await.unwrap();
+        let body = if text.is_empty() { Value::Null } else { serde_json::from_str(&text).unwrap() };
+
+        (status, body)
+    }
+
+    pub async fn signup(&self, email: &str) {
+        let (status, body) = self.post("/signup", None, json!({
+            "email": email,
+            "display_name": email.split('@').next().unwrap(),
+            "password": PASSWORD,
+        })).await;
+
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+    }
+
+    /// Confirms the address with the code from the latest mail sent to it.
+    pub async fn verify(&self, email: &str) {
+        let code = self.mailer.last_code_for(email).unwrap();
+        let (status, body) = self.post("/verify-email", None, json!({ "token": code })).await;
+
+        assert_eq!(status, StatusCode::OK, "{body}");
+    }
+
+    pub async fn login(&self, email: &str) -> (StatusCode, Value) {
+        self.post("/login", None, json!({ "email": email, "password": PASSWORD })).await
+    }
+
+    /// Signs up with a verified address, as most flows need one, and logs in.
+    pub async fn signup_and_login(&self, email: &str) -> TestUser {
+        self.signup(email).await;
+        self.verify(email).await;
+
+        let (status, body) = self.login(email).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+
+        TestUser {
+            email: email.to_string(),
+            token: body["token"].as_str().unwrap().to_string(),
+            refresh_token: body["refresh_token"].as_str().unwrap().to_string(),
+        }
+    }
+
+    /// Creates a project owned by `owner` and returns its id as the server serialized it.
+    pub async fn create_project(&self, owner: &TestUser, invitations: &[&str]) -> Value {
+        let (status, body) = self.post("/create-project", Some(&owner.token), json!({
+            "project_name": "Project",
+            "template_link": "template",
+            "tier_container_html": "<div>tiers</div>",
+            "image_carousel_html": "<div>images</div>",
+            "initial_invitations": invitations,
+        })).await;
+
+        assert_eq!(status, StatusCode::CREATED, "{body}");
+        body["project_id"].clone()
+    }
+
+    /// Creates an invite link to the project with `options` such as the role, and returns its token.
+    pub async fn create_invite_link(&self, owner: &TestUser, project_id: &Value, options: Value) -> String {
+        let mut request = json!({ "project_id": project_id });
+        request.as_object_mut().unwrap().extend(options.as_object().unwrap().clone());
+
+        let (status, body) = self.post("/create-invite-link", Some(&owner.token), request).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+
+        body["token"].as_str().unwrap().to_string()
+    }
+
+    pub async fn connect_ws(&self, token: &str) -> TestSocket {
+        let mut request = format!("ws://{}/ws", self.address).into_client_request().unwrap();
+        request.headers_mut().insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
+        );
+
+        let (stream, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+        TestSocket {
+            stream,
+        }
+    }
+}
+
+pub struct TestSocket {
+    stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl TestSocket {
+    pub async fn send(&mut self, message: Value) {
+        self.stream.send(Message::text(message.to_string())).await.unwrap();
+    }
+
+    /// The next server message, failing the test if none arrives in time.
+    pub async fn recv(&mut self) -> Value {
+        loop {
+            let message = tokio::time::timeout(RECV_TIMEOUT, self.stream.next())
+                .await
+                .expect("timed out waiting for a server message")
+                .expect("socket closed")
+                .unwrap();
+
+            if let Message::Text(text) = message {
+                return serde_json::from_str(text.as_str()).unwrap();
+            }
+        }
+    }
+
+    /// The close frame the server ends the socket with, failing the test if another message comes first.
+    pub async fn recv_close(&mut self) -> CloseFrame {
+        let message = tokio::time::timeout(RECV_TIMEOUT, self.stream.next())
+            .await
+            .expect("timed out waiting for the socket to close")
+            .expect("socket ended without a close frame")
+            .unwrap();
+
+        match message {
+            Message::Close(Some(frame)) => frame,
+            message => panic!("expected a close frame, got {message:?}"),
+        }
+    }
+
+    /// Skips messages until one of the given `type` arrives.
+    pub async fn recv_type(&mut self, message_type: &str) -> Value {
+        loop {
+            let message = self.recv().await;
+
+            if message["type"] == message_type {
+                return message;
+            }
+        }
+    }
+
+    pub async fn open_project(&mut self, project_id: &Value) -> Value {
+        self.send(json!({ "action": "open_project", "project_id": project_id })).await;
+        self.recv().await
+    }
+}
@@ -0,0 +1,549 @@
+//! Converts between a `TierList` and the HTML blobs older clients store.
+//!
+//! The parser is deliberately forgiving: it only looks for the markers below and ignores everything else.
+//!
+//! - A tier is an element with `data-tier-id` or the class `tier-row`. Its label comes from `data-label`
+//!   or the first descendant with the class `label`, and its color from `data-color` or the first
+//!   `background-color` style found on it or its descendants.
+//! - An item is an element with `data-item-id` or the class `item` or `character`, or a lone `<img>`.
+//!   Its image comes from `data-image`, an `<img src>` or a `background-image` url, and its label from
+//!   `data-label`, `alt`, `title` or its text.
+//! - Items inside a tier are placed there; all others go to the unplaced pool.
+
+use super::{is_hex_color, Item, Tier, TierList, DEFAULT_TIER_COLOR};
+use std::collections::HashSet;
+use std::mem;
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track", "wbr",
+];
+
+/// Legacy HTML can nest arbitrarily deep, so nothing below recurses per level of the tree.
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+            .filter(|value| !value.is_empty())
+    }
+
+    fn has_class(&self, class: &str) -> bool {
+        self.attribute("class").is_some_and(|classes| classes.split_whitespace().any(|c| c == class))
+    }
+
+    fn style(&self, property: &str) -> Option<&str> {
+        self.attribute("style")?
+            .split(';')
+            .filter_map(|declaration| declaration.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case(property))
+            .map(|(_, value)| value.trim())
+    }
+
+    fn is_tier(&self) -> bool {
+        self.attribute("data-tier-id").is_some() || self.has_class("tier-row")
+    }
+
+    fn is_item(&self) -> bool {
+        self.attribute("data-item-id").is_some()
+            || self.has_class("item")
+            || self.has_class("character")
+            || self.name == "img"
+    }
+
+    fn text(&self) -> String {
+        let mut text = String::new();
+        collect_text(&self.children, &mut text);
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// The first element at or below this one that satisfies `predicate`, in document order.
+    fn find(&self, predicate: &dyn Fn(&Element) -> bool) -> Option<&Element> {
+        if predicate(self) {
+            return Some(self);
+        }
+
+        let mut stack = vec![self.children.iter()];
+
+        while let Some(nodes) = stack.last_mut() {
+            match nodes.next() {
+                None => {
+                    stack.pop();
+                }
+                Some(Node::Element(element)) if predicate(element) => return Some(element),
+                Some(Node::Element(element)) => stack.push(element.children.iter()),
+                Some(Node::Text(_)) => {}
+            }
+        }
+
+        None
+    }
+}
+
+impl Drop for Element {
+    /// Flattens the subtree before it is freed, as the default drop would recurse once per level.
+    fn drop(&mut self) {
+        let mut nodes = mem::take(&mut self.children);
+
+        while let Some(node) = nodes.pop() {
+            if let Node::Element(mut element) = node {
+                nodes.append(&mut element.children);
+            }
+        }
+    }
+}
+
+fn collect_text(nodes: &[Node], text: &mut String) {
+    let mut stack = vec![nodes.iter()];
+
+    while let Some(nodes) = stack.last_mut() {
+        match nodes.next() {
+            None => {
+                stack.pop();
+            }
+            Some(Node::Text(chunk)) => {
+                text.push_str(chunk);
+                text.push(' ');
+            }
+            Some(Node::Element(element)) => stack.push(element.children.iter()),
+        }
+    }
+}
+
+/// Builds a tree from `html`, closing whatever the markup leaves open.
+fn parse_fragment(html: &str) -> Vec<Node> {
+    let mut stack = vec![Element::default()];
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+        } else if let Some(end_tag) = rest.strip_prefix("</") {
+            let end = end_tag.find('>').unwrap_or(end_tag.len());
+            let name = end_tag[..end].trim().to_ascii_lowercase();
+            rest = end_tag.get(end + 1..).unwrap_or("");
+
+            // Only close an element that is actually open; stray end tags are dropped.
+            if let Some(position) = stack.iter().skip(1).rposition(|element| element.name == name) {
+                while stack.len() > position + 1 {
+                    close_element(&mut stack);
+                }
+            }
+        } else if rest.starts_with('<') && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let (element, self_closing, remainder) = parse_start_tag(&rest[1..]);
+            rest = remainder;
+
+            if self_closing || VOID_ELEMENTS.contains(&element.name.as_str()) {
+                push_node(&mut stack, Node::Element(element));
+            } else {
+                stack.push(element);
+            }
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+        } else {
+            // Step over the first character so a stray `<` ends up in the text instead of looping forever.
+            let skip = rest.chars().next().map_or(0, char::len_utf8);
+            let end = rest[skip..].find('<').map_or(rest.len(), |end| end + skip);
+            push_node(&mut stack, Node::Text(decode_entities(&rest[..end])));
+            rest = &rest[end..];
+        }
+    }
+
+    while stack.len() > 1 {
+        close_element(&mut stack);
+    }
+
+    stack.pop().map(|mut root| mem::take(&mut root.children)).unwrap_or_default()
+}
+
+fn push_node(stack: &mut [Element], node: Node) {
+    if let Some(parent) = stack.last_mut() {
+        parent.children.push(node);
+    }
+}
+
+fn close_element(stack: &mut Vec<Element>) {
+    if let Some(element) = stack.pop() {
+        push_node(stack, Node::Element(element));
+    }
+}
+
+/// Reads a start tag after its `<`, returning the element, whether it closed itself and the remaining input.
+fn parse_start_tag(input: &str) -> (Element, bool, &str) {
+    let name_end = input.find(|c: char| c.is_whitespace() || c == '>' || c == '/').unwrap_or(input.len());
+    let mut element = Element {
+        name: input[..name_end].to_ascii_lowercase(),
+        attributes: vec![],
+        children: vec![],
+    };
+    let mut rest = &input[name_end..];
+
+    loop {
+        rest = rest.trim_start();
+
+        if let Some(remainder) = rest.strip_prefix("/>") {
+            return (element, true, remainder);
+        }
+
+        if let Some(remainder) = rest.strip_prefix('>') {
+            return (element, false, remainder);
+        }
+
+        if rest.is_empty() {
+            return (element, false, rest);
+        }
+
+        if let Some(remainder) = rest.strip_prefix('/') {
+            rest = remainder;
+            continue;
+        }
+
+        let name_end = rest.find(|c: char| c.is_whitespace() || matches!(c, '=' | '>' | '/')).unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let value = match rest.strip_prefix('=') {
+            None => String::new(),
+            Some(remainder) => {
+                let remainder = remainder.trim_start();
+
+                match remainder.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let quoted = &remainder[1..];
+                        let end = quoted.find(quote).unwrap_or(quoted.len());
+                        rest = quoted.get(end + 1..).unwrap_or("");
+                        decode_entities(&quoted[..end])
+                    }
+                    _ => {
+                        let end = remainder.find(|c: char| c.is_whitespace() || c == '>').unwrap_or(remainder.len());
+                        rest = &remainder[end..];
+                        decode_entities(&remainder[..end])
+                    }
+                }
+            }
+        };
+
+        element.attributes.push((name, value));
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Turns `rgb(255, 127, 127)` or a hex color into `#rrggbb`/`#rgb`.
+fn parse_color(value: &str) -> Option<String> {
+    let value = value.trim();
+
+    if is_hex_color(value) {
+        return Some(value.to_ascii_lowercase());
+    }
+
+    let channels = value.strip_prefix("rgb(").or_else(|| value.strip_prefix("rgba("))?.strip_suffix(')')?;
+    let channels: Vec<u8> = channels.split(',')
+        .take(3)
+        .map(|channel| channel.trim().parse().ok())
+        .collect::<Option<_>>()?;
+
+    match channels[..] {
+        [r, g, b] => Some(format!("#{r:02x}{g:02x}{b:02x}")),
+        _ => None,
+    }
+}
+
+/// The url inside `url(...)`, without quotes.
+fn parse_url(value: &str) -> Option<String> {
+    let inner = value.trim().strip_prefix("url(")?.strip_suffix(')')?.trim();
+    Some(inner.trim_matches(|c| c == '"' || c == '\'').to_string())
+}
+
+/// Hands out ids, replacing missing or repeated ones so the result always validates.
+struct Ids {
+    used: HashSet<String>,
+    prefix: &'static str,
+    next: usize,
+}
+
+impl Ids {
+    fn new(prefix: &'static str) -> Ids {
+        Ids {
+            used: HashSet::new(),
+            prefix,
+            next: 1,
+        }
+    }
+
+    fn claim(&mut self, preferred: Option<&str>) -> String {
+        if let Some(id) = preferred.filter(|id| !self.used.contains(*id)) {
+            self.used.insert(id.to_string());
+            return id.to_string();
+        }
+
+        loop {
+            let id = format!("{}-{}", self.prefix, self.next);
+            self.next += 1;
+
+            if self.used.insert(id.clone()) {
+                return id;
+            }
+        }
+    }
+}
+
+struct Extractor {
+    tier_list: TierList,
+    tier_ids: Ids,
+    item_ids: Ids,
+}
+
+impl Extractor {
+    /// Walks `nodes`, adding tiers as they appear and placing items in the tier they are in or the pool.
+    fn walk(&mut self, nodes: &[Node]) {
+        // Each level remembers the tier it is inside, if any.
+        let mut stack: Vec<(std::slice::Iter<Node>, Option<usize>)> = vec![(nodes.iter(), None)];
+
+        while let Some((nodes, tier_index)) = stack.last_mut() {
+            let tier_index = *tier_index;
+
+            let Some(node) = nodes.next() else {
+                stack.pop();
+                continue;
+            };
+
+            let Node::Element(element) = node else {
+                continue;
+            };
+
+            if tier_index.is_none() && element.is_tier() {
+                let index = self.add_tier(element);
+                stack.push((element.children.iter(), Some(index)));
+            } else if element.is_item() {
+                let item_id = self.add_item(element);
+
+                match tier_index {
+                    Some(index) => self.tier_list.tiers[index].items.push(item_id),
+                    None => self.tier_list.unplaced.push(item_id),
+                }
+            } else {
+                stack.push((element.children.iter(), tier_index));
+            }
+        }
+    }
+
+    fn add_tier(&mut self, element: &Element) -> usize {
+        let label = element.attribute("data-label")
+            .map(str::to_string)
+            .or_else(|| element.find(&|e| e.has_class("label")).map(Element::text))
+            .unwrap_or_default();
+
+        let color = element.attribute("data-color")
+            .and_then(parse_color)
+            .or_else(|| element.find(&|e| e.style("background-color").is_some())
+                .and_then(|e| e.style("background-color"))
+                .and_then(parse_color))
+            .unwrap_or_else(|| DEFAULT_TIER_COLOR.to_string());
+
+        let index = self.tier_list.tiers.len();
+
+        self.tier_list.tiers.push(Tier {
+            id: self.tier_ids.claim(element.attribute("data-tier-id")),
+            label,
+            color,
+            order: index as i64,
+            items: vec![],
+        });
+
+        index
+    }
+
+    fn add_item(&mut self, element: &Element) -> String {
+        let img = element.find(&|e| e.name == "img");
+
+        let image = element.attribute("data-image")
+            .or_else(|| img.and_then(|img| img.attribute("src")))
+            .map(str::to_string)
+            .or_else(|| element.style("background-image").and_then(parse_url))
+            .unwrap_or_default();
+
+        let label = element.attribute("data-label")
+            .or_else(|| img.and_then(|img| img.attribute("alt")))
+            .or_else(|| element.attribute("title"))
+            .map(str::to_string)
+            .unwrap_or_else(|| element.text());
+
+        let id = self.item_ids.claim(element.attribute("data-item-id").or_else(|| element.attribute("id")));
+
+        self.tier_list.items.push(Item {
+            id: id.clone(),
+            image,
+            label,
+        });
+
+        id
+    }
+}
+
+/// Reads a tier list out of the legacy tier container and image carousel HTML.
+///
+/// Anything the parser does not recognise is skipped, so the result is always a valid tier list.
+pub fn parse_tier_list(tier_container_html: &str, image_carousel_html: &str) -> TierList {
+    let mut extractor = Extractor {
+        tier_list: TierList::default(),
+        tier_ids: Ids::new("tier"),
+        item_ids: Ids::new("item"),
+    };
+
+    extractor.walk(&parse_fragment(tier_container_html));
+    extractor.walk(&parse_fragment(image_carousel_html));
+
+    extractor.tier_list
+}
+
+fn render_item(html: &mut String, item: &Item) {
+    html.push_str(&format!(
+        r#"<div class="item" data-item-id="{}" data-label="{}"><img src="{}" alt="{}"></div>"#,
+        escape(&item.id), escape(&item.label), escape(&item.image), escape(&item.label),
+    ));
+}
+
+/// Renders `tier_list` as tier container and image carousel HTML that `parse_tier_list` reads back unchanged.
+pub fn render_tier_list(tier_list: &TierList) -> (String, String) {
+    let item = |id: &String| tier_list.items.iter().find(|item| &item.id == id);
+    let mut tier_container_html = String::new();
+
+    for tier in tier_list.ordered_tiers() {
+        tier_container_html.push_str(&format!(
+            r#"<div class="tier-row" data-tier-id="{}" data-label="{}" data-color="{}" style="background-color: {}"><span class="label">{}</span>"#,
+            escape(&tier.id), escape(&tier.label), escape(&tier.color), escape(&tier.color), escape(&tier.label),
+        ));
+
+        for item in tier.items.iter().filter_map(item) {
+            render_item(&mut tier_container_html, item);
+        }
+
+        tier_container_html.push_str("</div>");
+    }
+
+    let mut image_carousel_html = String::new();
+
+    for item in tier_list.unplaced.iter().filter_map(item) {
+        render_item(&mut image_carousel_html, item);
+    }
+
+    (tier_container_html, image_carousel_html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_markup_is_parsed() {
+        let tiers = r#"
+            <div class="tier-row">
+                <div class="label-holder" style="background-color: rgb(255, 127, 127)"><span class="label">S &amp; up</span></div>
+                <div class="tier sort">
+                    <div class="character" id="cat" style="background-image: url('https://example.com/cat.png')" title="Cat"></div>
+                    <img src="https://example.com/dog.png" alt="Dog">
+                </div>
+            </div>
+            <div class="tier-row"><span class="label">A</span><div class="tier sort"></div></div>
+        "#;
+        let carousel = r#"<div id="create-image-carousel"><!-- pool --><div class="character" id="cat"><img src="bird.png"/></div></div>"#;
+
+        let tier_list = parse_tier_list(tiers, carousel);
+
+        assert_eq!(tier_list.tiers.len(), 2);
+        assert_eq!(tier_list.tiers[0].label, "S & up");
+        assert_eq!(tier_list.tiers[0].color, "#ff7f7f");
+        assert_eq!(tier_list.tiers[1].color, DEFAULT_TIER_COLOR);
+        assert_eq!(tier_list.tiers[0].items, vec!["cat", "item-1"]);
+        assert_eq!(tier_list.items[0].image, "https://example.com/cat.png");
+        assert_eq!(tier_list.items[1].label, "Dog");
+
+        // The repeated id in the carousel gets a fresh one rather than breaking the list.
+        assert_eq!(tier_list.unplaced, vec!["item-2"]);
+        assert_eq!(tier_list.items[2].image, "bird.png");
+        assert!(tier_list.validate().is_ok());
+    }
+
+    #[test]
+    fn unrecognised_markup_gives_an_empty_list() {
+        let tier_list = parse_tier_list("<div>tiers</div>", "<p>images <b>unclosed");
+
+        assert_eq!(tier_list, TierList::default());
+    }
+
+    #[test]
+    fn deeply_nested_markup_does_not_overflow_the_stack() {
+        const DEPTH: usize = 100_000;
+
+        let tiers = format!(
+            r#"<div class="tier-row">{}<div class="item" data-image="cat.png">{}Cat{}</div>{}</div>"#,
+            "<div>".repeat(DEPTH), "<span>".repeat(DEPTH), "</span>".repeat(DEPTH), "</div>".repeat(DEPTH),
+        );
+        let unclosed = "<div>".repeat(DEPTH);
+
+        let tier_list = parse_tier_list(&tiers, &unclosed);
+
+        assert_eq!(tier_list.tiers.len(), 1);
+        assert_eq!(tier_list.tiers[0].label, "");
+        assert_eq!(tier_list.tiers[0].items, vec!["item-1"]);
+        assert_eq!(tier_list.items[0].label, "Cat");
+        assert!(tier_list.unplaced.is_empty());
+    }
+
+    #[test]
+    fn rendered_html_parses_back_to_the_same_list() {
+        let tier_list = TierList {
+            tiers: vec![
+                Tier {
+                    id: "a".to_string(),
+                    label: "\"Best\" <3".to_string(),
+                    color: "#f00".to_string(),
+                    order: 0,
+                    items: vec!["x".to_string()],
+                },
+                Tier { id: "b".to_string(), label: "B".to_string(), color: "#00ff00".to_string(), order: 1, items: vec![] },
+            ],
+            items: vec![
+                Item { id: "x".to_string(), image: "x.png?a=1&b=2".to_string(), label: "Ünïcode".to_string() },
+                Item { id: "y".to_string(), image: "y.png".to_string(), label: String::new() },
+            ],
+            unplaced: vec!["y".to_string()],
+        };
+
+        let (tiers, carousel) = render_tier_list(&tier_list);
+
+        assert_eq!(parse_tier_list(&tiers, &carousel), tier_list);
+    }
+}
@@ -0,0 +1,208 @@
+//! The structured form of a tier list, which replaces the raw HTML the client used to send.
+//!
+//! During the transition both forms are stored: edits in either shape are converted to the other, so
+//! old and new clients can work on the same project.
+
+mod html;
+mod ops;
+
+pub use html::{parse_tier_list, render_tier_list};
+pub use ops::TierListOp;
+
+use crate::error;
+use crate::error::SharedTierListError::Validation;
+use crate::store::Store;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Used for tiers whose color could not be read from legacy HTML.
+pub const DEFAULT_TIER_COLOR: &str = "#cccccc";
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct TierList {
+    pub tiers: Vec<Tier>,
+    pub items: Vec<Item>,
+    /// Ids of the items not placed in any tier, in pool order.
+    #[serde(default)]
+    pub unplaced: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tier {
+    pub id: String,
+    pub label: String,
+    /// A `#rgb` or `#rrggbb` hex color.
+    pub color: String,
+    /// Tiers are shown in ascending order, top to bottom.
+    pub order: i64,
+    /// Ids of the items placed in this tier, left to right.
+    #[serde(default)]
+    pub items: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Item {
+    pub id: String,
+    /// URL of the item's picture.
+    pub image: String,
+    #[serde(default)]
+    pub label: String,
+}
+
+impl TierList {
+    /// Checks that ids are unique, orders are distinct, colors are hex and every item is placed exactly once.
+    pub fn validate(&self) -> error::Result<()> {
+        let mut tier_ids = HashSet::new();
+        let mut orders = HashSet::new();
+
+        for tier in &self.tiers {
+            if tier.id.is_empty() || !tier_ids.insert(tier.id.as_str()) {
+                return Err(Validation(format!("Tier id {:?} is empty or used twice", tier.id)));
+            }
+
+            if !orders.insert(tier.order) {
+                return Err(Validation(format!("Two tiers share the order {}", tier.order)));
+            }
+
+            if !is_hex_color(&tier.color) {
+                return Err(Validation(format!("Tier color {:?} is not a hex color", tier.color)));
+            }
+        }
+
+        let mut item_ids = HashSet::new();
+
+        for item in &self.items {
+            if item.id.is_empty() || !item_ids.insert(item.id.as_str()) {
+                return Err(Validation(format!("Item id {:?} is empty or used twice", item.id)));
+            }
+        }
+
+        let mut placed = HashSet::new();
+
+        for item_id in self.tiers.iter().flat_map(|tier| &tier.items).chain(&self.unplaced) {
+            if !item_ids.contains(item_id.as_str()) {
+                return Err(Validation(format!("Placement refers to unknown item {item_id:?}")));
+            }
+
+            if !placed.insert(item_id.as_str()) {
+                return Err(Validation(format!("Item {item_id:?} is placed more than once")));
+            }
+        }
+
+        if placed.len() != item_ids.len() {
+            return Err(Validation("Every item must be placed in a tier or left unplaced".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// The tiers in display order.
+    pub fn ordered_tiers(&self) -> Vec<&Tier> {
+        let mut tiers: Vec<&Tier> = self.tiers.iter().collect();
+        tiers.sort_by_key(|tier| tier.order);
+        tiers
+    }
+}
+
+pub fn is_hex_color(color: &str) -> bool {
+    color.strip_prefix('#')
+        .is_some_and(|hex| matches!(hex.len(), 3 | 6) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Gives every project that predates the structured model a tier list parsed from its HTML.
+///
+/// Returns how many projects were migrated. Projects edited meanwhile are left alone.
+pub async fn migrate_legacy_tier_lists(store: &dyn Store) -> error::Result<usize> {
+    let mut migrated = 0;
+
+    for project in store.projects_without_tier_list().await? {
+        let tier_list = parse_tier_list(&project.tier_container_html, &project.image_carousel_html);
+
+        if store.set_tier_list(project.id, &tier_list).await? {
+            migrated += 1;
+        }
+    }
+
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Project;
+    use crate::store::{MemoryStore, ProjectStore};
+    use crate::test_fixtures;
+    use mongodb::bson::oid::ObjectId;
+
+    fn tier_list() -> TierList {
+        TierList {
+            tiers: vec![Tier {
+                id: "s".to_string(),
+                label: "S".to_string(),
+                color: "#ff7f7f".to_string(),
+                order: 0,
+                items: vec!["cat".to_string()],
+            }],
+            items: vec![
+                Item { id: "cat".to_string(), image: "https://example.com/cat.png".to_string(), label: "Cat".to_string() },
+                Item { id: "dog".to_string(), image: "https://example.com/dog.png".to_string(), label: "Dog".to_string() },
+            ],
+            unplaced: vec!["dog".to_string()],
+        }
+    }
+
+    #[test]
+    fn well_formed_list_is_valid() {
+        assert!(tier_list().validate().is_ok());
+    }
+
+    #[test]
+    fn items_must_be_placed_exactly_once() {
+        let mut twice = tier_list();
+        twice.unplaced.push("cat".to_string());
+        assert!(twice.validate().is_err());
+
+        let mut missing = tier_list();
+        missing.unplaced.clear();
+        assert!(missing.validate().is_err());
+
+        let mut unknown = tier_list();
+        unknown.unplaced.push("bird".to_string());
+        assert!(unknown.validate().is_err());
+    }
+
+    #[test]
+    fn tier_colors_must_be_hex() {
+        let mut named = tier_list();
+        named.tiers[0].color = "red".to_string();
+
+        assert!(named.validate().is_err());
+        assert!(is_hex_color("#abc"));
+        assert!(!is_hex_color("#abcd"));
+    }
+
+    fn project(tier_container_html: &str, tier_list: Option<TierList>) -> Project {
+        Project {
+            tier_container_html: tier_container_html.to_string(),
+            tier_list,
+            ..test_fixtures::project(ObjectId::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn migration_parses_only_projects_without_a_tier_list() {
+        let store = MemoryStore::new();
+        let legacy = project(r#"<div class="tier-row"><span class="label">S</span></div>"#, None);
+        let current = project("", Some(TierList::default()));
+
+        store.insert_project(&legacy).await.unwrap();
+        store.insert_project(&current).await.unwrap();
+
+        assert_eq!(migrate_legacy_tier_lists(&store).await.unwrap(), 1);
+        assert_eq!(migrate_legacy_tier_lists(&store).await.unwrap(), 0);
+
+        let migrated = store.find_project(legacy.id).await.unwrap().unwrap().tier_list.unwrap();
+        assert_eq!(migrated.tiers[0].label, "S");
+        assert_eq!(store.find_project(current.id).await.unwrap().unwrap().tier_list, Some(TierList::default()));
+    }
+}
@@ -0,0 +1,229 @@
+//! Small edits to a tier list, which clients send instead of the whole list.
+
+use super::{is_hex_color, Item, Tier, TierList};
+use crate::error;
+use crate::error::SharedTierListError::Validation;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum TierListOp {
+    /// Puts an item at `index` in `to_tier`, or in the unplaced pool when `to_tier` is null.
+    ///
+    /// An index past the end appends, so moves still land when a peer just took an item out.
+    MoveItem {
+        item_id: String,
+        to_tier: Option<String>,
+        index: usize,
+    },
+    /// Adds an empty tier below the others.
+    AddTier {
+        tier_id: String,
+        label: String,
+        color: String,
+    },
+    RenameTier {
+        tier_id: String,
+        label: String,
+    },
+    /// Lists every tier id once, top to bottom.
+    ReorderTiers {
+        tier_ids: Vec<String>,
+    },
+    /// Deletes an item wherever it is placed.
+    RemoveItem {
+        item_id: String,
+    },
+    /// Adds an item to the end of the unplaced pool.
+    AddItem {
+        item_id: String,
+        image: String,
+        #[serde(default)]
+        label: String,
+    },
+}
+
+impl TierListOp {
+    /// Applies the operation, leaving `tier_list` untouched if it does not fit the current state.
+    pub fn apply(&self, tier_list: &mut TierList) -> error::Result<()> {
+        match self {
+            TierListOp::MoveItem { item_id, to_tier, index } => {
+                if !tier_list.items.iter().any(|item| &item.id == item_id) {
+                    return Err(Validation(format!("Item {item_id:?} does not exist")));
+                }
+
+                if let Some(tier_id) = to_tier {
+                    tier(tier_list, tier_id)?;
+                }
+
+                unplace(tier_list, item_id);
+
+                let placements = match to_tier {
+                    Some(tier_id) => &mut tier(tier_list, tier_id)?.items,
+                    None => &mut tier_list.unplaced,
+                };
+
+                placements.insert((*index).min(placements.len()), item_id.clone());
+            }
+            TierListOp::AddTier { tier_id, label, color } => {
+                if tier_id.is_empty() || tier_list.tiers.iter().any(|tier| &tier.id == tier_id) {
+                    return Err(Validation(format!("Tier id {tier_id:?} is empty or already used")));
+                }
+
+                if !is_hex_color(color) {
+                    return Err(Validation(format!("Tier color {color:?} is not a hex color")));
+                }
+
+                let order = match tier_list.tiers.iter().map(|tier| tier.order).max() {
+                    Some(last) => last.checked_add(1)
+                        .ok_or_else(|| Validation("No order is left below the last tier".to_string()))?,
+                    None => 0,
+                };
+
+                tier_list.tiers.push(Tier {
+                    id: tier_id.clone(),
+                    label: label.clone(),
+                    color: color.clone(),
+                    order,
+                    items: vec![],
+                });
+            }
+            TierListOp::RenameTier { tier_id, label } => {
+                tier(tier_list, tier_id)?.label = label.clone();
+            }
+            TierListOp::ReorderTiers { tier_ids } => {
+                let requested: HashSet<&String> = tier_ids.iter().collect();
+                let existing: HashSet<&String> = tier_list.tiers.iter().map(|tier| &tier.id).collect();
+
+                if requested.len() != tier_ids.len() || requested != existing {
+                    return Err(Validation("Reordering must list every tier exactly once".to_string()));
+                }
+
+                for tier in tier_list.tiers.iter_mut() {
+                    tier.order = tier_ids.iter().position(|id| id == &tier.id).unwrap_or_default() as i64;
+                }
+            }
+            TierListOp::RemoveItem { item_id } => {
+                let before = tier_list.items.len();
+                tier_list.items.retain(|item| &item.id != item_id);
+
+                if tier_list.items.len() == before {
+                    return Err(Validation(format!("Item {item_id:?} does not exist")));
+                }
+
+                unplace(tier_list, item_id);
+            }
+            TierListOp::AddItem { item_id, image, label } => {
+                if item_id.is_empty() || tier_list.items.iter().any(|item| &item.id == item_id) {
+                    return Err(Validation(format!("Item id {item_id:?} is empty or already used")));
+                }
+
+                tier_list.items.push(Item {
+                    id: item_id.clone(),
+                    image: image.clone(),
+                    label: label.clone(),
+                });
+                tier_list.unplaced.push(item_id.clone());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn tier<'a>(tier_list: &'a mut TierList, tier_id: &str) -> error::Result<&'a mut Tier> {
+    tier_list.tiers.iter_mut()
+        .find(|tier| tier.id == tier_id)
+        .ok_or_else(|| Validation(format!("Tier {tier_id:?} does not exist")))
+}
+
+/// Takes the item out of whichever tier or pool holds it.
+fn unplace(tier_list: &mut TierList, item_id: &str) {
+    for tier in tier_list.tiers.iter_mut() {
+        tier.items.retain(|id| id != item_id);
+    }
+
+    tier_list.unplaced.retain(|id| id != item_id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tier_list() -> TierList {
+        let op_list = [
+            TierListOp::AddTier { tier_id: "s".to_string(), label: "S".to_string(), color: "#f00".to_string() },
+            TierListOp::AddTier { tier_id: "a".to_string(), label: "A".to_string(), color: "#0f0".to_string() },
+            TierListOp::AddItem { item_id: "cat".to_string(), image: "cat.png".to_string(), label: String::new() },
+            TierListOp::AddItem { item_id: "dog".to_string(), image: "dog.png".to_string(), label: String::new() },
+        ];
+
+        let mut tier_list = TierList::default();
+        for op in op_list {
+            op.apply(&mut tier_list).unwrap();
+        }
+        tier_list
+    }
+
+    fn move_item(item_id: &str, to_tier: Option<&str>, index: usize) -> TierListOp {
+        TierListOp::MoveItem {
+            item_id: item_id.to_string(),
+            to_tier: to_tier.map(str::to_string),
+            index,
+        }
+    }
+
+    #[test]
+    fn moves_keep_every_item_placed_once() {
+        let mut tier_list = tier_list();
+
+        move_item("cat", Some("s"), 0).apply(&mut tier_list).unwrap();
+        move_item("dog", Some("s"), 0).apply(&mut tier_list).unwrap();
+        move_item("cat", Some("s"), 0).apply(&mut tier_list).unwrap();
+        move_item("dog", None, 99).apply(&mut tier_list).unwrap();
+
+        assert_eq!(tier_list.tiers[0].items, vec!["cat"]);
+        assert_eq!(tier_list.unplaced, vec!["dog"]);
+        assert!(tier_list.validate().is_ok());
+    }
+
+    #[test]
+    fn invalid_ops_leave_the_list_alone() {
+        let mut tier_list = tier_list();
+        let before = tier_list.clone();
+
+        assert!(move_item("cat", Some("z"), 0).apply(&mut tier_list).is_err());
+        assert!(move_item("bird", None, 0).apply(&mut tier_list).is_err());
+        assert!(TierListOp::ReorderTiers { tier_ids: vec!["a".to_string(), "a".to_string()] }.apply(&mut tier_list).is_err());
+        assert!(TierListOp::RemoveItem { item_id: "bird".to_string() }.apply(&mut tier_list).is_err());
+
+        assert_eq!(tier_list, before);
+    }
+
+    #[test]
+    fn adding_a_tier_after_the_largest_order_fails() {
+        let mut tier_list = tier_list();
+        tier_list.tiers[1].order = i64::MAX;
+        let before = tier_list.clone();
+
+        let add = TierListOp::AddTier { tier_id: "b".to_string(), label: "B".to_string(), color: "#00f".to_string() };
+
+        assert!(add.apply(&mut tier_list).is_err());
+        assert_eq!(tier_list, before);
+    }
+
+    #[test]
+    fn reorder_and_remove() {
+        let mut tier_list = tier_list();
+        move_item("cat", Some("a"), 0).apply(&mut tier_list).unwrap();
+
+        TierListOp::ReorderTiers { tier_ids: vec!["a".to_string(), "s".to_string()] }.apply(&mut tier_list).unwrap();
+        TierListOp::RemoveItem { item_id: "cat".to_string() }.apply(&mut tier_list).unwrap();
+
+        let labels: Vec<&str> = tier_list.ordered_tiers().iter().map(|tier| tier.label.as_str()).collect();
+        assert_eq!(labels, vec!["A", "S"]);
+        assert!(tier_list.tiers.iter().all(|tier| tier.items.is_empty()));
+        assert!(tier_list.validate().is_ok());
+    }
+}
@@ -0,0 +1,19 @@
+use chrono::Duration;
+use mongodb::bson::DateTime;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Random opaque token handed to clients; only its hash is ever stored.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub fn expires_after(duration: Duration) -> DateTime {
+    DateTime::from_millis(DateTime::now().timestamp_millis() + duration.num_milliseconds())
+}
@@ -0,0 +1,132 @@
+use crate::authentication::AuthenticatedUser;
+use crate::config::Config;
+use crate::error::SharedTierListError;
+use crate::error::SharedTierListError::{Forbidden, NotFound};
+use crate::store::Store;
+use crate::token::expires_after;
+use crate::{error, AppState};
+use axum::extract::State;
+use crate::extract::Json;
+use chrono::Duration;
+use http::StatusCode;
+use mongodb::bson::oid::ObjectId;
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+/// How often the purge job looks for projects past their retention period.
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+#[derive(Serialize)]
+pub struct TrashedProjectsResponse {
+    projects: Vec<TrashedProject>,
+}
+
+#[derive(Serialize)]
+struct TrashedProject {
+    project_id: ObjectId,
+    name: String,
+    template_link: String,
+    deleted_at: String,
+}
+
+#[derive(Deserialize)]
+pub struct RestoreProjectRequest {
+    project_id: ObjectId,
+}
+
+/// The caller's own projects that are in the trash.
+pub async fn list_trashed_projects(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+) -> error::Result<Json<TrashedProjectsResponse>> {
+    let projects = app_state.store.trashed_projects(user_id)
+        .await?
+        .into_iter()
+        .filter_map(|project| Some(TrashedProject {
+            deleted_at: project.deleted_at?.to_string(),
+            project_id: project.id,
+            name: project.name,
+            template_link: project.template_link,
+        }))
+        .collect();
+
+    Ok(Json(TrashedProjectsResponse {
+        projects
+    }))
+}
+
+pub async fn restore_project(
+    State(app_state): State<Arc<AppState>>,
+    AuthenticatedUser { user_id, .. }: AuthenticatedUser,
+    Json(payload): Json<RestoreProjectRequest>,
+) -> error::Result<StatusCode> {
+    let project_opt = app_state.store.find_project(payload.project_id).await?;
+
+    let Some(project) = project_opt.filter(|project| project.is_trashed()) else {
+        return Err(NotFound("Project not found in the trash".to_string()));
+    };
+
+    if project.owner != user_id {
+        return Err(Forbidden("Only the project owner can restore it".to_string()));
+    }
+
+    if !app_state.store.restore_project(project.id).await? {
+        return Err(NotFound("Project not found in the trash".to_string()));
+    }
+
+    tracing::debug!("Restored project {} from the trash", project.id);
+
+    Ok(StatusCode::OK)
+}
+
+/// What one run of `purge_trash` got through.
+#[derive(Debug, Default)]
+pub struct PurgeReport {
+    pub purged: usize,
+    /// Projects whose purge failed; they stay in the trash and the next run tries them again.
+    pub failed: Vec<(ObjectId, SharedTierListError)>,
+}
+
+/// Hard-deletes every project trashed before `cutoff`.
+///
+/// Projects restored after being listed are skipped, since the store checks the cutoff again. A project that
+/// fails to purge is logged and left for the next run, so it cannot hold up the ones after it.
+pub async fn purge_trash(store: &dyn Store, cutoff: DateTime) -> error::Result<PurgeReport> {
+    let mut report = PurgeReport::default();
+
+    for project_id in store.projects_trashed_before(cutoff).await? {
+        match store.purge_project(project_id, cutoff).await {
+            Ok(true) => report.purged += 1,
+            Ok(false) => {}
+            Err(e) => {
+                tracing::error!("Could not purge project {project_id}: {e}");
+                report.failed.push((project_id, e));
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Runs `purge_trash` periodically with the retention period from `config`.
+pub fn spawn_trash_purge(config: &Config, store: Arc<dyn Store>) -> JoinHandle<()> {
+    let retention = Duration::days(config.trash_retention_days);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            match purge_trash(store.as_ref(), expires_after(-retention)).await {
+                Ok(PurgeReport { purged: 0, failed }) if failed.is_empty() => {}
+                Ok(PurgeReport { purged, failed }) => {
+                    tracing::debug!("Purged {purged} projects from the trash, {} failed", failed.len());
+                }
+                Err(e) => tracing::error!("Could not purge the trash: {e}"),
+            }
+        }
+    })
+}
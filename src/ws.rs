@@ -1,75 +1,153 @@
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
 use tokio::sync::broadcast::Receiver;
+use tokio::sync::broadcast::error::RecvError;
 use axum::extract::{State, WebSocketUpgrade};
-use axum::extract::ws::{Message, WebSocket};
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
 use axum_core::response::IntoResponse;
-use axum_extra::TypedHeader;
 use futures_util::{SinkExt, StreamExt};
 use futures_util::stream::{SplitSink, SplitStream};
-use headers::Authorization;
-use headers::authorization::Bearer;
 use http::{StatusCode};
-use mongodb::bson::{doc, Document};
 use mongodb::bson::oid::{ObjectId};
-use tokio::sync::{broadcast, Mutex};
+use tokio::sync::{broadcast, mpsc, Mutex};
 use tokio::sync::broadcast::Sender;
-use crate::{error, AppState, ProjectContents};
-use crate::authentication::authenticate_user;
-use crate::db_constants::{Collections, ProjectFields, UserFields};
+use crate::{error, AppState};
+use crate::authentication::AuthSession;
+use crate::authorization::authorize_project;
+use crate::revisions::REVISION_COALESCE_WINDOW;
+use crate::session::session_is_active;
+use crate::models::{Project, Revision};
+use crate::store::RevisionSource;
+use crate::tier_list::TierListOp;
+use crate::token::expires_after;
+use crate::error::SharedTierListError;
 use crate::error::SharedTierListError::StatusCodeError;
-use crate::ws_types::{ClientMessage, ProjectContentsResponse};
+use crate::ws_types::{ClientMessage, ClientRequest, ErrorCode, ProjectContentsResponse, ServerMessage};
 
 
 struct WebSocketState {
     user_id: ObjectId,
+    session_id: ObjectId,
     project: Arc<Mutex<WebSocketProject>>,
+    outbound: mpsc::UnboundedSender<Outbound>,
+    permission_violations: AtomicU32,
+}
+
+impl WebSocketState {
+    fn send(&self, message: ServerMessage) {
+        let _ = self.outbound.send(Outbound::Message(message));
+    }
 }
 
 struct WebSocketProject {
     project_id: Option<ObjectId>,
-    rx: Option<Receiver<ProjectContentsResponse>>,
-    tx: Option<Sender<ProjectContentsResponse>>,
+    live_session: Option<LiveSession>,
+}
+
+/// Shared by every socket that has the same project open.
+#[derive(Clone)]
+pub struct LiveSession {
+    tx: Sender<ServerMessage>,
+    /// Held from reading the project to writing it back, so concurrent operations all land.
+    edit_lock: Arc<Mutex<()>>,
+}
+
+/// Handed from the receive task to the send task, which owns the socket's write half.
+enum Outbound {
+    /// Sends `opened` and only then listens on `rx`, so no broadcast can overtake the snapshot it follows.
+    Subscribe {
+        project_id: ObjectId,
+        rx: Receiver<ServerMessage>,
+        opened: ServerMessage,
+    },
+    Message(ServerMessage),
+    Close,
+}
+
+const MAX_PERMISSION_VIOLATIONS: u32 = 3;
+
+/// Why an edit was not applied. A member whose role cannot edit is refused without counting as a violation.
+enum EditError {
+    ReadOnly,
+    Failed(SharedTierListError),
 }
 
-async fn shared_session_broadcast_sender(
+impl From<SharedTierListError> for EditError {
+    fn from(e: SharedTierListError) -> EditError {
+        EditError::Failed(e)
+    }
+}
+
+/// Subscribes to the project's shared session, starting it if needed.
+///
+/// The receiver is created under the map lock so `release_session` cannot see the session as unused
+/// between joining it and subscribing.
+async fn shared_live_session(
     app_state: Arc<AppState>,
     project_id: ObjectId
-) -> error::Result<Sender<ProjectContentsResponse>> {
+) -> error::Result<(LiveSession, Receiver<ServerMessage>)> {
     tracing::debug!("Getting session");
 
     let mut live_sessions_guard = app_state.live_sessions.lock().await;
 
     match live_sessions_guard.get(&project_id) {
-        Some(tx) => {
+        Some(live_session) => {
             tracing::debug!("Session already started");
-            Ok(tx.clone())
+            Ok((live_session.clone(), live_session.tx.subscribe()))
         }
         None => {
             tracing::debug!("Session not yet started");
-            const BROADCAST_CHANNEL_CAPACITY: usize = 64;
-            let (tx, _rx) = broadcast::channel(BROADCAST_CHANNEL_CAPACITY);
-            live_sessions_guard.insert(project_id, tx.clone());
+            let (tx, rx) = broadcast::channel(app_state.broadcast_channel_capacity);
+            let live_session = LiveSession {
+                tx,
+                edit_lock: Arc::new(Mutex::new(())),
+            };
+            live_sessions_guard.insert(project_id, live_session.clone());
             tracing::debug!("Session created");
-            Ok(tx)
+            Ok((live_session, rx))
         }
     }
 }
 
-async fn check_project_permissions(
-    app_state: Arc<AppState>,
-    socket_state: Arc<WebSocketState>,
+/// Drops the shared session for a project once no socket is subscribed to it anymore.
+async fn release_session(app_state: Arc<AppState>, project_id: ObjectId) {
+    let mut live_sessions_guard = app_state.live_sessions.lock().await;
+
+    if let Some(live_session) = live_sessions_guard.get(&project_id) {
+        if live_session.tx.receiver_count() == 0 {
+            live_sessions_guard.remove(&project_id);
+            tracing::debug!("Session closed");
+        }
+    }
+}
+
+/// The lock edits to an open project take; `None` when nobody has it open, so nothing can race.
+pub async fn project_edit_lock(app_state: &AppState, project_id: ObjectId) -> Option<Arc<Mutex<()>>> {
+    app_state.live_sessions.lock().await
+        .get(&project_id)
+        .map(|live_session| live_session.edit_lock.clone())
+}
+
+/// Sends new contents to every socket on the project, as if a member had edited it.
+pub async fn broadcast_project_update(
+    app_state: &AppState,
     project_id: ObjectId,
-) -> error::Result<()> {
-    let user_opt  = app_state.db
-        .collection::<Document>(Collections::USERS)
-        .find_one(doc! {
-            UserFields::ID: socket_state.user_id,
-            UserFields::PROJECTS: project_id,
-        }).await?;
+    version: i64,
+    contents: ProjectContentsResponse,
+) {
+    if let Some(live_session) = app_state.live_sessions.lock().await.get(&project_id) {
+        let _ = live_session.tx.send(ServerMessage::ProjectUpdated { project_id, version, contents });
+    }
+}
+
+/// Tells every socket on a project that it is gone and ends its live session.
+///
+/// Each socket forgets the project when the message reaches it, and has to open it again once restored.
+pub async fn notify_project_deleted(app_state: &AppState, project_id: ObjectId) {
+    let live_session_opt = app_state.live_sessions.lock().await.remove(&project_id);
 
-    match user_opt {
-        None => Err(StatusCodeError(StatusCode::INTERNAL_SERVER_ERROR)),
-        Some(_) => Ok(())
+    if let Some(live_session) = live_session_opt {
+        let _ = live_session.tx.send(ServerMessage::ProjectDeleted { project_id });
     }
 }
 
@@ -77,31 +155,74 @@ async fn open_project(
     app_state: Arc<AppState>,
     socket_state: Arc<WebSocketState>,
     project_id: ObjectId,
+    request_id: Option<String>,
 ) -> error::Result<()> {
-    let projects = app_state.db.collection::<Document>(Collections::PROJECTS);
-    let project_opt = projects.find_one(doc! { ProjectFields::ID: project_id }).await?;
-
-    match project_opt {
-        None => Err(StatusCodeError(StatusCode::INTERNAL_SERVER_ERROR)),
-        Some(project) => {
-            if let Ok(tx) = shared_session_broadcast_sender(app_state.clone(), project_id).await {
-                let rx = tx.subscribe();
-
-                let mut project_guard = socket_state.project.lock().await;
-                project_guard.project_id = Some(project_id);
-                project_guard.tx = Some(tx.clone());
-                project_guard.rx = Some(rx);
-                drop(project_guard);
-
-                let _ = tx.send(ProjectContentsResponse {
-                    tier_container_html: project.get_str(ProjectFields::TIER_CONTAINER_HTML)?.to_string(),
-                    image_carousel_html: project.get_str(ProjectFields::IMAGE_CAROUSEL_HTML)?.to_string(),
-                });
-            }
-
-            Ok(())
+    // Subscribing before reading means no edit can land between the contents sent here and the broadcasts
+    // that follow. Broadcasts already included in the contents have a version up to the one sent with them.
+    let (live_session, rx) = shared_live_session(app_state.clone(), project_id).await?;
+
+    let project = match authorize_project(app_state.store.as_ref(), socket_state.user_id, project_id).await {
+        Ok((project, _)) => project,
+        Err(e) => {
+            drop(rx);
+            release_session(app_state, project_id).await;
+            return Err(e);
         }
+    };
+
+    let opened = ServerMessage::ProjectOpened {
+        request_id,
+        project_id,
+        version: project.version,
+        contents: project.contents(),
+    };
+
+    let mut project_guard = socket_state.project.lock().await;
+    project_guard.project_id = Some(project_id);
+    project_guard.live_session = Some(live_session);
+    drop(project_guard);
+
+    socket_state.outbound
+        .send(Outbound::Subscribe { project_id, rx, opened })
+        .map_err(|_| StatusCodeError(StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    Ok(())
+}
+
+async fn current_live_session(socket_state: &WebSocketState) -> error::Result<LiveSession> {
+    socket_state.project.lock().await.live_session.clone()
+        .ok_or(StatusCodeError(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+/// Returns the project as it is now, provided the caller may still edit it.
+async fn authorize_edit(
+    app_state: &AppState,
+    socket_state: &WebSocketState,
+    project_id: ObjectId,
+) -> Result<Project, EditError> {
+    // Membership is re-checked on every edit so removed or demoted members lose write access immediately.
+    let (project, role) = authorize_project(app_state.store.as_ref(), socket_state.user_id, project_id).await?;
+
+    if !role.can_edit() {
+        return Err(EditError::ReadOnly);
     }
+
+    Ok(project)
+}
+
+async fn save_edit(
+    app_state: &AppState,
+    socket_state: &WebSocketState,
+    project_id: ObjectId,
+    contents: &ProjectContentsResponse,
+) -> error::Result<Revision> {
+    let source = RevisionSource::Edit {
+        coalesce_since: expires_after(-REVISION_COALESCE_WINDOW),
+    };
+
+    app_state.store.save_revision(project_id, socket_state.user_id, contents, source)
+        .await?
+        .ok_or_else(|| SharedTierListError::NotFound("Project not found".to_string()))
 }
 
 async fn edit_project(
@@ -109,21 +230,176 @@ async fn edit_project(
     socket_state: Arc<WebSocketState>,
     project_id: ObjectId,
     project_contents: ProjectContentsResponse,
-) -> error::Result<()> {
-    let projects = app_state.db.collection::<Document>(Collections::PROJECTS);
-    projects.update_one(
-        doc! { ProjectFields::ID: project_id },
-        doc! {
-            ProjectFields::TIER_CONTAINER_HTML: project_contents.tier_container_html.clone(),
-            ProjectFields::IMAGE_CAROUSEL_HTML: project_contents.image_carousel_html.clone()
+) -> Result<(), EditError> {
+    let live_session = current_live_session(&socket_state).await?;
+    let _edit_guard = live_session.edit_lock.lock().await;
+
+    authorize_edit(&app_state, &socket_state, project_id).await?;
+    let revision = save_edit(&app_state, &socket_state, project_id, &project_contents).await?;
+
+    let _ = live_session.tx.send(ServerMessage::ProjectUpdated {
+        project_id,
+        version: revision.version,
+        contents: project_contents,
+    });
+
+    Ok(())
+}
+
+/// Applies one operation to the stored tier list and sends only the operation to the project's sockets.
+async fn apply_op(
+    app_state: Arc<AppState>,
+    socket_state: Arc<WebSocketState>,
+    project_id: ObjectId,
+    op: TierListOp,
+) -> Result<(), EditError> {
+    let live_session = current_live_session(&socket_state).await?;
+    let _edit_guard = live_session.edit_lock.lock().await;
+
+    let project = authorize_edit(&app_state, &socket_state, project_id).await?;
+
+    let mut tier_list = project.contents().tier_list;
+    op.apply(&mut tier_list)?;
+
+    let contents = ProjectContentsResponse::from_tier_list(tier_list)?;
+    let revision = save_edit(&app_state, &socket_state, project_id, &contents).await?;
+
+    let _ = live_session.tx.send(ServerMessage::OperationApplied {
+        project_id,
+        author: socket_state.user_id,
+        version: revision.version,
+        op,
+    });
+
+    Ok(())
+}
+
+/// Translates a failed request into the frame the client is told about.
+fn error_message(
+    request_id: Option<String>,
+    project_id: ObjectId,
+    e: SharedTierListError,
+) -> ServerMessage {
+    match e.status_code() {
+        StatusCode::BAD_REQUEST => ServerMessage::Error {
+            request_id,
+            code: ErrorCode::InvalidMessage,
+            message: e.to_string(),
+        },
+        StatusCode::FORBIDDEN => ServerMessage::PermissionDenied {
+            request_id,
+            project_id,
+        },
+        StatusCode::NOT_FOUND => ServerMessage::Error {
+            request_id,
+            code: ErrorCode::ProjectNotFound,
+            message: "Project not found".to_string(),
+        },
+        _ => {
+            tracing::debug!("{e}");
+            ServerMessage::Error {
+                request_id,
+                code: ErrorCode::InternalError,
+                message: "Internal server error".to_string(),
+            }
+        }
+    }
+}
+
+/// Sends the error frame and closes the socket once a client keeps hitting projects it cannot access.
+fn report_error(
+    socket_state: &WebSocketState,
+    request_id: Option<String>,
+    project_id: ObjectId,
+    e: SharedTierListError,
+) {
+    let message = error_message(request_id, project_id, e);
+
+    if let ServerMessage::PermissionDenied { .. } = message {
+        let violations = socket_state.permission_violations.fetch_add(1, Ordering::SeqCst) + 1;
+        socket_state.send(message);
+
+        if violations >= MAX_PERMISSION_VIOLATIONS {
+            tracing::debug!("Closing socket for user {} after {violations} permission violations", socket_state.user_id);
+            let _ = socket_state.outbound.send(Outbound::Close);
+        }
+    } else {
+        socket_state.send(message);
+    }
+}
+
+/// Acknowledges an edit, or tells the client why it was not applied.
+fn report_edit(
+    socket_state: &WebSocketState,
+    request_id: Option<String>,
+    project_id: ObjectId,
+    result: Result<(), EditError>,
+) {
+    match result {
+        Ok(()) => socket_state.send(ServerMessage::Ack { request_id }),
+        Err(EditError::ReadOnly) => socket_state.send(ServerMessage::Error {
+            request_id,
+            code: ErrorCode::ReadOnly,
+            message: "Your role cannot edit this project".to_string(),
+        }),
+        Err(EditError::Failed(e)) => report_error(socket_state, request_id, project_id, e),
+    }
+}
+
+/// The project this socket has open, telling the client off if there is none.
+async fn open_project_id(socket_state: &WebSocketState, request_id: &Option<String>) -> Option<ObjectId> {
+    let project_id_opt = socket_state.project.lock().await.project_id;
+
+    if project_id_opt.is_none() {
+        socket_state.send(ServerMessage::Error {
+            request_id: request_id.clone(),
+            code: ErrorCode::NoProjectOpen,
+            message: "Open a project before editing it".to_string(),
+        });
+    }
+
+    project_id_opt
+}
+
+async fn handle_client_message(
+    app_state: Arc<AppState>,
+    socket_state: Arc<WebSocketState>,
+    request_id: Option<String>,
+    message: ClientMessage,
+) {
+    match message {
+        ClientMessage::OpenProject {
+            project_id
+        } => {
+            if let Err(e) = open_project(app_state.clone(), socket_state.clone(), project_id, request_id.clone()).await {
+                report_error(&socket_state, request_id, project_id, e);
+            }
         }
-    ).await?;
+        ClientMessage::EditProject {
+            tier_container_html,
+            image_carousel_html,
+            tier_list,
+        } => {
+            let Some(project_id) = open_project_id(&socket_state, &request_id).await else {
+                return;
+            };
+
+            let contents = ProjectContentsResponse::from_request(tier_container_html, image_carousel_html, tier_list);
+
+            let result = match contents {
+                Ok(contents) => edit_project(app_state.clone(), socket_state.clone(), project_id, contents).await,
+                Err(e) => Err(e.into()),
+            };
+
+            report_edit(&socket_state, request_id, project_id, result);
+        }
+        ClientMessage::Op(op) => {
+            let Some(project_id) = open_project_id(&socket_state, &request_id).await else {
+                return;
+            };
 
-    match socket_state.project.lock().await.tx.clone() {
-        None => Err(StatusCodeError(StatusCode::INTERNAL_SERVER_ERROR))
-        Some(tx) => {
-            let _ = tx.send(project_contents);
-            Ok(())
+            let result = apply_op(app_state.clone(), socket_state.clone(), project_id, op).await;
+            report_edit(&socket_state, request_id, project_id, result);
         }
     }
 }
@@ -133,104 +409,218 @@ async fn socket_recv_task(
     socket_state: Arc<WebSocketState>,
     mut receiver: SplitStream<WebSocket>
 ) {
-    while let Some(Ok(Message::Text(text))) = receiver.next().await {
-        if let Ok(msg) = serde_json::from_str::<ClientMessage>(text.as_str()) {
-            match msg {
-                ClientMessage::OpenProject {
-                    project_id
-                } => {
-                    if let Err(e) = check_project_permissions(app_state.clone(), socket_state.clone(), project_id).await {
-                        tracing::debug!("{e}");
-                    }
-
-                    if let Err(e) = open_project(app_state.clone(), socket_state.clone(), project_id).await {
-                        tracing::debug!("{e}");
-                    }
-                }
-                ClientMessage::EditProject {
-                    tier_container_html,
-                    image_carousel_html
-                } => {
-                    let project_guard = socket_state.project.lock().await;
+    while let Some(Ok(message)) = receiver.next().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+
+        match serde_json::from_str::<ClientRequest>(text.as_str()) {
+            Ok(request) => {
+                handle_client_message(
+                    app_state.clone(),
+                    socket_state.clone(),
+                    request.request_id,
+                    request.message,
+                ).await;
+            }
+            Err(e) => {
+                socket_state.send(ServerMessage::Error {
+                    request_id: None,
+                    code: ErrorCode::InvalidMessage,
+                    message: e.to_string(),
+                });
+            }
+        }
+    }
+}
 
-                    if let Some(project_id) = project_guard.project_id {
-                        drop(project_guard);
+/// Detaches the socket from a deleted project, so a restore does not leave it editing through the ended session.
+async fn forget_project(socket_state: &WebSocketState, project_id: ObjectId) {
+    let mut project_guard = socket_state.project.lock().await;
 
-                        if let Err(e) = check_project_permissions(app_state.clone(), socket_state.clone(), project_id).await {
-                            tracing::debug!("{e}");
-                        }
+    // The receive task may have opened another project meanwhile, which must stay.
+    if project_guard.project_id == Some(project_id) {
+        project_guard.project_id = None;
+        project_guard.live_session = None;
+    }
+}
 
-                        if let Err(e) = edit_project(
-                            app_state.clone(),
-                            socket_state.clone(),
-                            project_id,
-                            ProjectContentsResponse {
-                                tier_container_html,
-                                image_carousel_html
-                            }
-                        ).await {
-                            tracing::debug!("{e}");
-                        }
-                    }
-                }
-            }
-        }
+/// Waits on the current project's broadcast, or forever if no project is open yet.
+async fn next_broadcast(
+    rx_opt: &mut Option<Receiver<ServerMessage>>
+) -> Result<ServerMessage, RecvError> {
+    match rx_opt {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
     }
 }
 
+async fn send_message(
+    sender: &mut SplitSink<WebSocket, Message>,
+    message: &ServerMessage,
+) -> error::Result<()> {
+    let text = serde_json::to_string(message)
+        .map_err(|_| StatusCodeError(StatusCode::INTERNAL_SERVER_ERROR))?;
+
+    sender.send(Message::Text(text.into())).await
+        .map_err(|_| StatusCodeError(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+async fn close_socket(sender: &mut SplitSink<WebSocket, Message>, reason: &'static str) {
+    let _ = sender.send(Message::Close(Some(CloseFrame {
+        code: close_code::POLICY,
+        reason: reason.into(),
+    }))).await;
+}
+
 async fn socket_send_task(
     app_state: Arc<AppState>,
     socket_state: Arc<WebSocketState>,
+    mut outbound: mpsc::UnboundedReceiver<Outbound>,
+    mut revocations: Receiver<ObjectId>,
     mut sender: SplitSink<WebSocket, Message>
 ) {
-    // let project_guard = socket_state.project.lock().await;
-    // let rx = project_guard.rx
-
-    while let Ok(msg) = socket_state.rx.recv().await {
-        match msg {
-            ClientMessage::OpenProject {
-                project_id
-            } => {
-                shared_session_broadcast_sender()
-                sender.send()
-            }
-            ClientMessage::EditProject {
-                tier_container_html,
-                image_carousel_html
-            } => {
+    let mut project_id_opt: Option<ObjectId> = None;
+    let mut rx_opt: Option<Receiver<ServerMessage>> = None;
+
+    loop {
+        // Revocations go first, then broadcasts before queued replies: an edit is broadcast before it is
+        // acknowledged, so the sender always sees its own update ahead of the ack.
+        let message = tokio::select! {
+            biased;
+
+            revocation = revocations.recv() => {
+                let revoked = match revocation {
+                    Ok(session_id) => session_id == socket_state.session_id,
+                    Err(RecvError::Lagged(_)) => {
+                        // A missed revocation may have been ours, so ask the database instead.
+                        !session_is_active(&app_state, socket_state.user_id, socket_state.session_id).await
+                            .unwrap_or(false)
+                    }
+                    Err(RecvError::Closed) => false,
+                };
 
-            }
+                if revoked {
+                    close_socket(&mut sender, "Session revoked").await;
+                    break;
+                }
+
+                continue;
+            },
+            broadcast = next_broadcast(&mut rx_opt) => match broadcast {
+                Ok(ServerMessage::ProjectDeleted { project_id }) => {
+                    forget_project(&socket_state, project_id).await;
+                    rx_opt = None;
+                    project_id_opt = None;
+                    ServerMessage::ProjectDeleted { project_id }
+                }
+                Ok(message) => message,
+                Err(RecvError::Lagged(skipped)) => {
+                    // Operations only make sense in sequence, so resend the whole project after missing any.
+                    tracing::debug!("Socket lagged behind by {skipped} updates");
+
+                    let Some(project_id) = project_id_opt else {
+                        continue;
+                    };
+
+                    // Skip what is still buffered, as the snapshot read next already contains it. Anything
+                    // sent between resubscribing and reading carries a version the client can tell apart.
+                    if let Some(rx) = rx_opt.as_mut() {
+                        *rx = rx.resubscribe();
+                    }
+
+                    match app_state.store.find_project(project_id).await {
+                        Ok(Some(project)) if !project.is_trashed() => ServerMessage::ProjectUpdated {
+                            project_id,
+                            version: project.version,
+                            contents: project.contents(),
+                        },
+                        // The missed updates may have included the deletion.
+                        Ok(_) => {
+                            forget_project(&socket_state, project_id).await;
+                            rx_opt = None;
+                            project_id_opt = None;
+                            ServerMessage::ProjectDeleted { project_id }
+                        }
+                        Err(_) => continue,
+                    }
+                }
+                Err(RecvError::Closed) => {
+                    rx_opt = None;
+                    continue;
+                }
+            },
+            outbound_opt = outbound.recv() => match outbound_opt {
+                None => break,
+                Some(Outbound::Subscribe { project_id, rx, opened }) => {
+                    if let Err(e) = send_message(&mut sender, &opened).await {
+                        tracing::debug!("{e}");
+                        break;
+                    }
+
+                    // Replacing the receiver unsubscribes from the previously opened project.
+                    rx_opt = Some(rx);
+
+                    if let Some(previous_project_id) = project_id_opt.replace(project_id) {
+                        if previous_project_id != project_id {
+                            release_session(app_state.clone(), previous_project_id).await;
+                        }
+                    }
+
+                    continue;
+                }
+                Some(Outbound::Message(message)) => message,
+                Some(Outbound::Close) => {
+                    close_socket(&mut sender, "Too many permission violations").await;
+                    break;
+                }
+            },
+        };
+
+        if let Err(e) = send_message(&mut sender, &message).await {
+            tracing::debug!("{e}");
+            break;
         }
     }
 }
 
 async fn handle_socket(
     user_id: ObjectId,
+    session_id: ObjectId,
+    revocations: Receiver<ObjectId>,
     socket: WebSocket,
     app_state: Arc<AppState>,
 ) {
     tracing::debug!("Upgraded");
 
     let (sender, receiver) = socket.split();
+    let (outbound_tx, outbound_rx) = mpsc::unbounded_channel();
 
     let socket_state = Arc::new(WebSocketState {
         user_id,
+        session_id,
         project: Arc::new(Mutex::new(WebSocketProject {
             project_id: None,
-            rx: None,
-            tx: None,
+            live_session: None,
         })),
+        outbound: outbound_tx,
+        permission_violations: AtomicU32::new(0),
     });
 
     let app_state_clone = app_state.clone();
     let socket_state_clone = socket_state.clone();
 
     let mut recv_task = tokio::spawn(async move {
-        socket_recv_task(app_state_clone, socket_state_clone, receiver)
+        socket_recv_task(app_state_clone, socket_state_clone, receiver).await
     });
 
+    let app_state_clone = app_state.clone();
+    let socket_state_clone = socket_state.clone();
+
     let mut send_task = tokio::spawn(async move {
-        socket_send_task(app_state.clone(), socket_state.clone(), sender)
+        socket_send_task(app_state_clone, socket_state_clone, outbound_rx, revocations, sender).await
     });
 
     tokio::select! {
@@ -238,15 +628,28 @@ async fn handle_socket(
         _ = &mut recv_task => { send_task.abort(); }
     }
 
+    // Both tasks are finished or aborted, so this socket's receiver has been dropped.
+    let _ = recv_task.await;
+    let _ = send_task.await;
+
+    let project_id = socket_state.project.lock().await.project_id;
+    if let Some(project_id) = project_id {
+        release_session(app_state, project_id).await;
+    }
 }
 
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     State(app_state): State<Arc<AppState>>,
-    TypedHeader(auth): TypedHeader<Authorization<Bearer>>
-) -> Result<impl IntoResponse, StatusCode> {
-    let user = authenticate_user(app_state.clone(), auth).await?;
-    let user_id = user.get_object_id(UserFields::ID).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    AuthSession { user_id, session_id }: AuthSession,
+) -> error::Result<impl IntoResponse> {
+    // Listening before checking the session again means a revocation after the extractor's check either fails
+    // the check or reaches the socket.
+    let revocations = app_state.session_revocations.subscribe();
+
+    if !session_is_active(&app_state, user_id, session_id).await? {
+        return Err(StatusCodeError(StatusCode::UNAUTHORIZED));
+    }
 
-    Ok(ws.on_upgrade(move |socket| handle_socket(user_id, socket, app_state)))
+    Ok(ws.on_upgrade(move |socket| handle_socket(user_id, session_id, revocations, socket, app_state)))
 }
@@ -1,5 +1,15 @@
+use crate::error;
+use crate::error::SharedTierListError::Validation;
+use crate::tier_list::{parse_tier_list, render_tier_list, TierList, TierListOp};
 use mongodb::bson::oid::ObjectId;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Deserialize)]
+pub struct ClientRequest {
+    pub(crate) request_id: Option<String>,
+    #[serde(flatten)]
+    pub(crate) message: ClientMessage,
+}
 
 #[derive(Debug, Deserialize)]
 #[serde(tag = "action", rename_all = "snake_case")]
@@ -7,15 +17,111 @@ pub enum ClientMessage {
     OpenProject {
         project_id: ObjectId
     },
+    /// Replaces the whole project, either as `tier_list` or, from older clients, as the two HTML fields.
     EditProject {
-        tier_container_html: String,
-        image_carousel_html: String,
-    }
+        tier_container_html: Option<String>,
+        image_carousel_html: Option<String>,
+        tier_list: Option<TierList>,
+    },
+    /// Any other action is one of the granular tier list operations.
+    #[serde(untagged)]
+    Op(TierListOp),
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    InvalidMessage,
+    NoProjectOpen,
+    ProjectNotFound,
+    /// The member's role only lets them view the project.
+    ReadOnly,
+    InternalError,
 }
 
+#[derive(Debug, Serialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    /// The project as of `version`; broadcasts that follow with a version up to it are already included.
+    ProjectOpened {
+        request_id: Option<String>,
+        project_id: ObjectId,
+        version: i64,
+        contents: ProjectContentsResponse,
+    },
+    /// The whole project as of `version`; operations with a version up to it are already included.
+    ProjectUpdated {
+        project_id: ObjectId,
+        version: i64,
+        contents: ProjectContentsResponse,
+    },
+    /// The request succeeded; any update it broadcast reached this socket first.
+    Ack {
+        request_id: Option<String>,
+    },
+    Error {
+        request_id: Option<String>,
+        code: ErrorCode,
+        message: String,
+    },
+    PermissionDenied {
+        request_id: Option<String>,
+        project_id: ObjectId,
+    },
+    ProjectDeleted {
+        project_id: ObjectId,
+    },
+    /// A peer's operation, already applied to the stored project, which it brought to `version`.
+    OperationApplied {
+        project_id: ObjectId,
+        author: ObjectId,
+        version: i64,
+        op: TierListOp,
+    },
+}
 
-#[derive(Debug, Deserialize, Clone)]
+/// A project's contents in both the legacy HTML and the structured shape, kept in step with each other.
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct ProjectContentsResponse {
     pub(crate) tier_container_html: String,
     pub(crate) image_carousel_html: String,
+    pub(crate) tier_list: TierList,
+}
+
+impl ProjectContentsResponse {
+    pub fn from_html(tier_container_html: String, image_carousel_html: String) -> ProjectContentsResponse {
+        ProjectContentsResponse {
+            tier_list: parse_tier_list(&tier_container_html, &image_carousel_html),
+            tier_container_html,
+            image_carousel_html,
+        }
+    }
+
+    /// Validates `tier_list` and renders the HTML older clients still read.
+    pub fn from_tier_list(tier_list: TierList) -> error::Result<ProjectContentsResponse> {
+        tier_list.validate()?;
+
+        let (tier_container_html, image_carousel_html) = render_tier_list(&tier_list);
+
+        Ok(ProjectContentsResponse {
+            tier_container_html,
+            image_carousel_html,
+            tier_list,
+        })
+    }
+
+    /// Contents from a request carrying either shape; the structured one wins when both are sent.
+    pub fn from_request(
+        tier_container_html: Option<String>,
+        image_carousel_html: Option<String>,
+        tier_list: Option<TierList>,
+    ) -> error::Result<ProjectContentsResponse> {
+        match (tier_list, tier_container_html, image_carousel_html) {
+            (Some(tier_list), _, _) => ProjectContentsResponse::from_tier_list(tier_list),
+            (None, Some(tier_container_html), Some(image_carousel_html)) => {
+                Ok(ProjectContentsResponse::from_html(tier_container_html, image_carousel_html))
+            }
+            _ => Err(Validation("Send either tier_list or both HTML fields".to_string())),
+        }
+    }
 }